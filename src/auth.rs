@@ -0,0 +1,205 @@
+//! Credentials for fetching password-protected pages, e.g. on a staging site
+//!
+//! Credentials are deliberately not accepted on the command line, where they
+//! would be visible to other users and saved in shell history. They come from
+//! the `[fetch.auth]` section of the config file or from environment variables.
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION, COOKIE};
+use serde::Deserialize;
+use std::fmt;
+use std::fs;
+use std::time::{SystemTime, UNIX_EPOCH};
+use url::Url;
+
+use crate::error::{Error, Result};
+
+/// Environment variable for the basic auth user name
+pub const USERNAME_VAR: &str = "HTMLTORSS_USERNAME";
+/// Environment variable for the basic auth password
+pub const PASSWORD_VAR: &str = "HTMLTORSS_PASSWORD";
+/// Environment variable for the bearer token
+pub const TOKEN_VAR: &str = "HTMLTORSS_TOKEN";
+/// Environment variable for the path of a Netscape format cookie file
+pub const COOKIE_FILE_VAR: &str = "HTMLTORSS_COOKIE_FILE";
+
+/// Credentials sent with HTTP requests
+#[derive(Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Auth {
+    /// User name for HTTP basic auth
+    pub username: Option<String>,
+    /// Password for HTTP basic auth
+    pub password: Option<String>,
+    /// Bearer token, used instead of basic auth if both are given
+    pub token: Option<String>,
+    /// Path of a Netscape format cookie file, as exported by browsers and curl
+    pub cookie_file: Option<String>,
+    /// The hosts the basic auth or bearer token credentials are sent to
+    /// (cookies go wherever the cookie file says)
+    pub hosts: Vec<String>,
+}
+
+// Keep secrets out of debug output and logs
+impl fmt::Debug for Auth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let redacted = |v: &Option<String>| v.as_ref().map(|_| "***");
+        f.debug_struct("Auth")
+            .field("username", &self.username)
+            .field("password", &redacted(&self.password))
+            .field("token", &redacted(&self.token))
+            .field("cookie_file", &self.cookie_file)
+            .field("hosts", &self.hosts)
+            .finish()
+    }
+}
+
+impl Auth {
+    /// Override these credentials with any set in environment variables
+    pub fn with_env(self) -> Self {
+        self.with_vars(|name| std::env::var(name).ok().filter(|v| !v.is_empty()))
+    }
+
+    /// Override these credentials with any returned by `var`
+    pub fn with_vars(self, var: impl Fn(&str) -> Option<String>) -> Self {
+        Auth {
+            username: var(USERNAME_VAR).or(self.username),
+            password: var(PASSWORD_VAR).or(self.password),
+            token: var(TOKEN_VAR).or(self.token),
+            cookie_file: var(COOKIE_FILE_VAR).or(self.cookie_file),
+            hosts: self.hosts,
+        }
+    }
+
+    /// Also send the credentials to the hosts of any URLs in `pages`
+    pub fn with_hosts_of(mut self, pages: &[String]) -> Self {
+        for page in pages {
+            let Ok(url) = Url::parse(page) else { continue };
+            if let ("http" | "https", Some(host)) = (url.scheme(), url.host_str()) {
+                if !self.hosts.iter().any(|h| h.eq_ignore_ascii_case(host)) {
+                    self.hosts.push(host.to_string());
+                }
+            }
+        }
+        self
+    }
+
+    /// The Authorization and Cookie headers to send with a request for `url`
+    pub fn headers(&self, url: &str) -> Result<HeaderMap> {
+        let mut headers = HeaderMap::new();
+        let authorization = match (&self.token, &self.username) {
+            (Some(token), _) => Some(format!("Bearer {}", token)),
+            (None, Some(username)) => {
+                let credentials = format!("{}:{}", username, self.password.as_deref().unwrap_or_default());
+                Some(format!("Basic {}", STANDARD.encode(credentials)))
+            }
+            (None, None) => None,
+        };
+        if let Some(authorization) = authorization.filter(|_| self.sends_credentials_to(url)) {
+            headers.insert(AUTHORIZATION, sensitive_value(&authorization)?);
+        }
+        if let Some(cookies) = self.cookies(url)? {
+            headers.insert(COOKIE, sensitive_value(&cookies)?);
+        }
+        Ok(headers)
+    }
+
+    /// Whether `url` is on one of the hosts the credentials are for
+    fn sends_credentials_to(&self, url: &str) -> bool {
+        let Ok(url) = Url::parse(url) else { return false };
+        url.host_str().is_some_and(|host| self.hosts.iter().any(|h| h.eq_ignore_ascii_case(host)))
+    }
+
+    /// The Cookie header value for `url` from the cookie file, if any apply
+    fn cookies(&self, url: &str) -> Result<Option<String>> {
+        let Some(cookie_file) = &self.cookie_file else { return Ok(None) };
+        let cookies = cookies_for(&fs::read_to_string(cookie_file)?, url);
+        Ok((!cookies.is_empty()).then_some(cookies))
+    }
+}
+
+/// A header value which is kept out of debug output
+fn sensitive_value(value: &str) -> Result<HeaderValue> {
+    let mut value = HeaderValue::from_str(value)
+        .map_err(|_| Error::Config("credentials can't contain control characters".to_string()))?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// The Cookie header value for `url` from the text of a Netscape format
+/// cookie file, i.e. lines of tab-separated domain, subdomains flag, path,
+/// secure flag, expiry time, name and value. Expired cookies are left out.
+pub fn cookies_for(cookie_file: &str, url: &str) -> String {
+    let Ok(url) = Url::parse(url) else {
+        return String::new();
+    };
+    let host = url.host_str().unwrap_or_default().to_ascii_lowercase();
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+
+    cookie_file.lines()
+        .filter_map(|line| {
+            // Lines starting #HttpOnly_ are cookies, other # lines are comments
+            let line = line.strip_prefix("#HttpOnly_").unwrap_or(line);
+            if line.starts_with('#') {
+                return None;
+            }
+            let fields: Vec<&str> = line.trim_end_matches(['\r', '\n']).split('\t').collect();
+            let [domain, subdomains, path, secure, expires, name, value] = fields[..] else {
+                return None;
+            };
+            let domain = domain.trim_start_matches('.').to_ascii_lowercase();
+            let domain_matches = host == domain || (subdomains == "TRUE" && host.ends_with(&format!(".{}", domain)));
+            let secure_ok = secure != "TRUE" || url.scheme() == "https";
+            // (NB: an expiry time of 0 is a session cookie)
+            let current = expires.parse::<u64>().map_or(true, |expires| expires == 0 || expires > now);
+            (domain_matches && url.path().starts_with(path) && secure_ok && current)
+                .then(|| format!("{}={}", name, value))
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COOKIES: &str = "# Netscape HTTP Cookie File\n\
+        .staging.site.com\tTRUE\t/\tFALSE\t0\tsession\tabc123\n\
+        #HttpOnly_staging.site.com\tFALSE\t/drafts\tTRUE\t0\tpreview\tyes\n\
+        other.com\tFALSE\t/\tFALSE\t0\tnope\t1\n\
+        staging.site.com\tTRUE\t/\tFALSE\t1\texpired\tyes\n\
+        staging.site.com\tTRUE\t/\tFALSE\t4102444800\tlater\tyes\n";
+
+    #[test]
+    fn cookies_matching_domain_path_and_scheme() {
+        assert_eq!(cookies_for(COOKIES, "https://staging.site.com/drafts/post.html"), "session=abc123; preview=yes; later=yes");
+        assert_eq!(cookies_for(COOKIES, "http://www.staging.site.com/drafts/post.html"), "session=abc123; later=yes");
+        assert_eq!(cookies_for(COOKIES, "https://www.staging.site.com/drafts/post.html"), "session=abc123; later=yes");
+        assert_eq!(cookies_for(COOKIES, "https://site.com/"), "");
+    }
+
+    #[test]
+    fn credentials_only_for_the_pages_hosts() {
+        let pages = ["https://staging.site.com/post.html".to_string(), "posts/local.html".to_string()];
+        let auth = Auth { token: Some("t0k3n".to_string()), ..Auth::default() }.with_hosts_of(&pages);
+        assert_eq!(auth.hosts, ["staging.site.com"]);
+        assert!(auth.headers("https://STAGING.site.com/audio.mp3").unwrap().contains_key(AUTHORIZATION));
+        assert!(auth.headers("https://cdn.example.com/audio.mp3").unwrap().is_empty());
+    }
+
+    #[test]
+    fn environment_overrides_config() {
+        let config = Auth { username: Some("config".to_string()), password: Some("pw".to_string()), ..Auth::default() };
+        let auth = config.with_vars(|name| (name == USERNAME_VAR).then(|| "env".to_string()));
+        assert_eq!(auth.username.as_deref(), Some("env"));
+        assert_eq!(auth.password.as_deref(), Some("pw"));
+    }
+
+    #[test]
+    fn secrets_not_in_debug_output() {
+        let auth = Auth { password: Some("hunter2".to_string()), token: Some("t0k3n".to_string()), ..Auth::default() };
+        let debug = format!("{:?}", auth);
+        assert!(!debug.contains("hunter2") && !debug.contains("t0k3n"));
+    }
+}
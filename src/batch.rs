@@ -0,0 +1,219 @@
+//! Reading many HTML pages at once
+//!
+//! Pages are read concurrently, up to a limit, with a minimum delay
+//! between requests to the same host so as not to hammer a site. The
+//! results always come back in the order the pages were given.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
+
+use crate::encoding::decode_html;
+use crate::error::{Error, Result};
+use crate::fetch::{local_path, FetchOptions, STDIN};
+
+/// Read all the pages in `sources`, as [`crate::fetch::read_html_with`]
+/// does for one, returning each page's HTML (or error) in the same order
+/// as `sources`. Standard input and repeated sources aren't allowed.
+pub fn read_all_html(sources: &[String], options: &FetchOptions) -> Result<Vec<Result<String>>> {
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(read_all_html_async(sources, options))
+}
+
+/// Async version of [`read_all_html`] for use inside a tokio runtime
+pub async fn read_all_html_async(sources: &[String], options: &FetchOptions) -> Result<Vec<Result<String>>> {
+    check_sources(sources)?;
+    let client = options.async_client().map_err(|source| Error::Fetch { url: sources.join(", "), source })?;
+    let options = Arc::new(options.clone());
+    let permits = Arc::new(Semaphore::new(options.concurrency.max(1)));
+    let limiter = HostLimiter::new(Duration::from_millis(options.host_delay_ms));
+
+    // Start them all, each waiting its turn for a permit and its host
+    let tasks: Vec<_> = sources.iter()
+        .map(|source| {
+            let (source, client, options) = (source.clone(), client.clone(), Arc::clone(&options));
+            let (permits, limiter) = (Arc::clone(&permits), limiter.clone());
+            tokio::spawn(async move {
+                let _permit = permits.acquire_owned().await.expect("semaphore is never closed");
+                limiter.wait(&source).await;
+                read_html_async(&client, &source, &options).await
+            })
+        })
+        .collect();
+
+    // Then collect the results in order, passing on any panic
+    let mut pages = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        pages.push(task.await.unwrap_or_else(|e| std::panic::resume_unwind(e.into_panic())));
+    }
+    Ok(pages)
+}
+
+/// Check there's no standard input (which can only be read once and
+/// not at the same time as other pages) and no source given twice
+pub fn check_sources(sources: &[String]) -> Result<()> {
+    if sources.len() > 1 && sources.iter().any(|s| s == STDIN) {
+        return Err(Error::Sources(format!("'{}' (standard input) can't be read along with other pages", STDIN)));
+    }
+    let mut seen = HashSet::new();
+    match sources.iter().find(|s| !seen.insert(s.as_str())) {
+        Some(repeated) => Err(Error::Sources(format!("{} is given more than once", repeated))),
+        None => Ok(()),
+    }
+}
+
+/// Read one page from a local file or URL
+async fn read_html_async(client: &reqwest::Client, source: &str, options: &FetchOptions) -> Result<String> {
+    match local_path(source)? {
+        Some(path) => decode_html(&tokio::fs::read(path).await?, None, options.encoding.as_deref()),
+        None => get_html_async(client, source, options).await,
+    }
+}
+
+/// Fetch a page as [`FetchOptions::get_html`] does, but asynchronously
+async fn get_html_async(client: &reqwest::Client, url: &str, options: &FetchOptions) -> Result<String> {
+    let fetch_error = |source| Error::Fetch { url: url.to_string(), source };
+    let lookup = options.cache_lookup(url)?;
+    if options.offline {
+        return options.decode(&lookup.offline_page(url)?);
+    }
+    options.ready_to_send(url)?;
+
+    // Send the request, retrying with exponential backoff on transient failures
+    let mut attempt = 0;
+    let response = loop {
+        let result = client.get(url)
+            .headers(lookup.conditional_headers())
+            .headers(options.auth.headers(url)?)
+            .send()
+            .await;
+        match options.retry_delay(result.as_ref().map(reqwest::Response::status), attempt) {
+            Some(delay) => tokio::time::sleep(delay).await,
+            None => break result.and_then(|r| r.error_for_status()).map_err(fetch_error)?,
+        }
+        attempt += 1;
+    };
+
+    let (status, headers) = (response.status(), response.headers().clone());
+    let body = response.bytes().await.map_err(fetch_error)?.to_vec();
+    options.decode(&lookup.page(url, status, &headers, body)?)
+}
+
+/// Spaces out requests to the same host
+#[derive(Debug, Clone)]
+struct HostLimiter {
+    delay: Duration,
+    /// The earliest time the next request to each host may start
+    next: Arc<Mutex<HashMap<String, Instant>>>,
+}
+
+impl HostLimiter {
+    fn new(delay: Duration) -> Self {
+        HostLimiter { delay, next: Arc::new(Mutex::new(HashMap::new())) }
+    }
+
+    /// Wait until a request to the source's host is allowed
+    /// (local files and stdin don't wait at all)
+    async fn wait(&self, source: &str) {
+        let Some(host) = host_of(source) else { return };
+
+        // Book the next free slot for the host then wait for it
+        let start = {
+            let mut next = self.next.lock().unwrap();
+            let now = Instant::now();
+            let start = next.get(&host).copied().filter(|t| *t > now).unwrap_or(now);
+            next.insert(host, start + self.delay);
+            start
+        };
+        tokio::time::sleep_until(start).await;
+    }
+}
+
+/// The host of an http(s) URL
+fn host_of(source: &str) -> Option<String> {
+    let url = url::Url::parse(source).ok()?;
+    match url.scheme() {
+        "http" | "https" => url.host_str().map(str::to_string),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fetch::test_server::serve;
+    use std::time::Instant;
+
+    #[test]
+    fn results_in_given_order() {
+        let dir = tempfile::tempdir().unwrap();
+        let sources: Vec<String> = (0..5)
+            .map(|i| {
+                let path = dir.path().join(format!("{}.html", i));
+                std::fs::write(&path, format!("<p>{}</p>", i)).unwrap();
+                path.to_string_lossy().into_owned()
+            })
+            .collect();
+        let options = FetchOptions { concurrency: 2, ..FetchOptions::default() };
+        let mut missing = sources.clone();
+        missing.insert(2, "no_such_page.html".to_string());
+
+        let pages = read_all_html(&missing, &options).unwrap();
+
+        assert!(pages[2].is_err());
+        let found: Vec<String> = pages.into_iter().filter_map(|page| page.ok()).collect();
+        assert_eq!(found, ["<p>0</p>", "<p>1</p>", "<p>2</p>", "<p>3</p>", "<p>4</p>"]);
+    }
+
+    #[test]
+    fn spaces_out_requests_to_a_host() {
+        let server = serve(vec![("200 OK", "", b"a"), ("200 OK", "", b"a"), ("200 OK", "", b"a")]);
+        let options = FetchOptions { concurrency: 3, host_delay_ms: 100, ..FetchOptions::default() };
+
+        let sources: Vec<String> = ["a", "b", "c"].iter().map(|page| format!("{}/{}", server.url, page)).collect();
+
+        let started = Instant::now();
+        let pages = read_all_html(&sources, &options).unwrap();
+        assert!(started.elapsed() >= Duration::from_millis(200));
+        assert!(pages.iter().all(|page| page.as_deref().ok() == Some("a")));
+    }
+
+    #[test]
+    fn fetches_asynchronously_with_cache_and_credentials() {
+        let server = serve(vec![
+            ("200 OK", "ETag: \"v1\"\r\nContent-Type: text/html; charset=ISO-8859-1\r\n", b"caf\xe9"),
+            ("304 Not Modified", "", b""),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = FetchOptions { cache_dir: Some(dir.path().to_path_buf()), ..FetchOptions::default() };
+        options.auth.token = Some("t0k3n".to_string());
+        options.auth.hosts = vec!["127.0.0.1".to_string()];
+
+        let first = read_all_html(std::slice::from_ref(&server.url), &options).unwrap();
+        let second = read_all_html(std::slice::from_ref(&server.url), &options).unwrap();
+
+        assert_eq!(first[0].as_deref().unwrap(), "café");
+        assert_eq!(second[0].as_deref().unwrap(), "café");
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].to_lowercase().contains("authorization: bearer t0k3n"));
+        assert!(requests[1].to_lowercase().contains("if-none-match: \"v1\""));
+    }
+
+    #[test]
+    fn rejects_stdin_and_repeated_sources() {
+        let options = FetchOptions::default();
+        let with_stdin = ["a.html".to_string(), STDIN.to_string()];
+        let repeated = ["a.html".to_string(), "b.html".to_string(), "a.html".to_string()];
+        assert!(matches!(read_all_html(&with_stdin, &options), Err(Error::Sources(_))));
+        assert!(matches!(read_all_html(&repeated, &options), Err(Error::Sources(_))));
+    }
+
+    #[test]
+    fn hosts() {
+        assert_eq!(host_of("https://site.com/a.html").as_deref(), Some("site.com"));
+        assert_eq!(host_of("file:///tmp/a.html"), None);
+        assert_eq!(host_of("posts/a.html"), None);
+    }
+}
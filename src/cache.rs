@@ -0,0 +1,108 @@
+//! On-disk cache of fetched pages, for conditional requests and offline runs
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::Result;
+
+/// A fetched page and the validators needed to check it is still current
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct CachedResponse {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_type: Option<String>,
+    /// (NB: stored after the JSON metadata line rather than in it)
+    #[serde(skip)]
+    pub body: Vec<u8>,
+}
+
+/// A directory of cached responses, keyed by URL
+#[derive(Debug, Clone)]
+pub struct Cache {
+    dir: PathBuf,
+}
+
+impl Cache {
+    /// Use the cache in `dir`, which is created when first written to
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Cache { dir: dir.into() }
+    }
+
+    /// The cached response for `url`, if there is one
+    pub fn get(&self, url: &str) -> Result<Option<CachedResponse>> {
+        let entry = match fs::read(self.path(url)) {
+            Ok(entry) => entry,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(e) => return Err(e.into()),
+        };
+
+        // Treat a damaged or mismatched entry as a miss, it'll be overwritten
+        let Some(newline) = entry.iter().position(|&b| b == b'\n') else { return Ok(None) };
+        let Ok(mut cached) = serde_json::from_slice::<CachedResponse>(&entry[..newline]) else { return Ok(None) };
+        if cached.url != url {
+            return Ok(None);
+        }
+        cached.body = entry[newline + 1..].to_vec();
+        Ok(Some(cached))
+    }
+
+    /// Store a response, replacing any earlier one for the same URL
+    pub fn put(&self, response: &CachedResponse) -> Result<()> {
+        fs::create_dir_all(&self.dir)?;
+
+        // Each entry is a line of JSON metadata followed by the body
+        let mut entry = serde_json::to_vec(response)?;
+        entry.push(b'\n');
+        entry.extend_from_slice(&response.body);
+
+        // Write a temporary file and rename it into place, so concurrent
+        // readers and writers never see half an entry
+        static WRITES: AtomicUsize = AtomicUsize::new(0);
+        let path = self.path(&response.url);
+        let temp = path.with_extension(format!("{}-{}.tmp", std::process::id(), WRITES.fetch_add(1, Ordering::Relaxed)));
+        fs::write(&temp, entry)?;
+        fs::rename(&temp, &path).inspect_err(|_| {
+            let _ = fs::remove_file(&temp);
+        })?;
+        Ok(())
+    }
+
+    /// The file path for a URL's entry
+    fn path(&self, url: &str) -> PathBuf {
+        let key: String = Sha256::digest(url.as_bytes())
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        self.dir.join(format!("{}.cache", key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn put_and_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = Cache::new(dir.path());
+        assert_eq!(cache.get("https://site/a.html").unwrap(), None);
+
+        let response = CachedResponse {
+            url: "https://site/a.html".to_string(),
+            etag: Some("\"abc\"".to_string()),
+            body: b"<p>Hi</p>".to_vec(),
+            ..CachedResponse::default()
+        };
+        cache.put(&response).unwrap();
+        let cached = cache.get("https://site/a.html").unwrap();
+        let other = cache.get("https://site/b.html").unwrap();
+
+        assert_eq!(cached, Some(response));
+        assert_eq!(other, None);
+    }
+}
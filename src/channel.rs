@@ -0,0 +1,147 @@
+//! Reading and updating the feed's channel-level metadata
+
+use crate::error::Result;
+use crate::feed::{find_child_element, Feed};
+use crate::utils;
+
+/// Channel fields which can be read and updated
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ChannelInfo {
+    pub title: Option<String>,
+    pub link: Option<String>,
+    pub description: Option<String>,
+    pub language: Option<String>,
+    /// URL of the channel's <image>
+    pub image: Option<String>,
+    /// Minutes a feed reader may cache the feed for
+    pub ttl: Option<u32>,
+    pub copyright: Option<String>,
+    /// Email address of the person responsible for the content
+    pub managing_editor: Option<String>,
+}
+
+impl ChannelInfo {
+    /// Read the channel fields from a feed
+    pub fn read(feed: &Feed) -> Self {
+        let text = |tag| feed.channel_element(tag).and_then(|xml| inner_text(xml, tag));
+        ChannelInfo {
+            title: text("title"),
+            link: text("link"),
+            description: text("description"),
+            language: text("language"),
+            image: feed.channel_element("image").and_then(|xml| {
+                // (NB: the <url> must be the image's own, so search inside it)
+                let children = &xml[xml.find('>')? + 1..];
+                find_child_element(children, "url").and_then(|(start, end)| inner_text(&children[start..end], "url"))
+            }),
+            ttl: text("ttl").and_then(|t| t.trim().parse().ok()),
+            copyright: text("copyright"),
+            managing_editor: text("managingEditor"),
+        }
+    }
+
+    /// Write the fields which are set to a feed, leaving the others alone
+    pub fn update(&self, feed: &mut Feed) -> Result<()> {
+        let simple = [
+            ("title", &self.title),
+            ("link", &self.link),
+            ("description", &self.description),
+            ("language", &self.language),
+            ("copyright", &self.copyright),
+            ("managingEditor", &self.managing_editor),
+        ];
+        for (tag, value) in simple {
+            if let Some(value) = value {
+                feed.set_channel_element(tag, &format!("<{0}>{1}</{0}>", tag, utils::escape_xml(value)))?;
+            }
+        }
+        if let Some(ttl) = self.ttl {
+            feed.set_channel_element("ttl", &format!("<ttl>{}</ttl>", ttl))?;
+        }
+
+        // An <image> must repeat the channel's title and link
+        if let Some(url) = &self.image {
+            let current = ChannelInfo::read(feed);
+            let image = format!(
+                "<image>\n            <url>{}</url>\n            <title>{}</title>\n            <link>{}</link>\n        </image>",
+                utils::escape_xml(url),
+                utils::escape_xml(current.title.as_deref().unwrap_or_default()),
+                utils::escape_xml(current.link.as_deref().unwrap_or_default()),
+            );
+            feed.set_channel_element("image", &image)?;
+        }
+        Ok(())
+    }
+}
+
+/// The unescaped text inside a simple `<tag>text</tag>` element
+fn inner_text(xml: &str, tag: &str) -> Option<String> {
+    let start = xml.find('>')? + 1;
+    let end = xml.rfind(&format!("</{}>", tag))?;
+    (start <= end).then(|| utils::unescape_xml(xml[start..end].trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::feed::test_feed::write_feed;
+
+    const RSS: &str = r#"<rss version="2.0">
+    <channel>
+        <title>My &amp; Blog</title>
+        <link>https://site/blog</link>
+        <description>Stuff</description>
+        <ttl>60</ttl>
+        <item><title>Item</title><link>https://site/blog/a.html</link></item>
+    </channel>
+</rss>
+"#;
+
+    fn load_feed(content: &str) -> Feed {
+        let (_dir, path) = write_feed(content);
+        Feed::load(&path).unwrap()
+    }
+
+    #[test]
+    fn read_channel_fields_not_item_fields() {
+        let info = ChannelInfo::read(&load_feed(RSS));
+        assert_eq!(info.title.as_deref(), Some("My & Blog"));
+        assert_eq!(info.link.as_deref(), Some("https://site/blog"));
+        assert_eq!(info.ttl, Some(60));
+        assert_eq!(info.language, None);
+    }
+
+    #[test]
+    fn image_fields_are_not_channel_fields() {
+        let mut feed = load_feed(&RSS.replace("<channel>\n", "<channel>\n        <image><url>https://s/logo.png</url><title>Logo Title</title><link>https://s/</link></image>\n"));
+
+        let info = ChannelInfo::read(&feed);
+        assert_eq!(info.title.as_deref(), Some("My & Blog"));
+        assert_eq!(info.link.as_deref(), Some("https://site/blog"));
+        assert_eq!(info.image.as_deref(), Some("https://s/logo.png"));
+
+        ChannelInfo { title: Some("New".to_string()), ..ChannelInfo::default() }.update(&mut feed).unwrap();
+        assert!(feed.content().contains("<image><url>https://s/logo.png</url><title>Logo Title</title>"));
+        assert!(feed.content().contains("        <title>New</title>\n"));
+    }
+
+    #[test]
+    fn update_preserves_everything_else() {
+        let mut feed = load_feed(RSS);
+        let update = ChannelInfo {
+            ttl: Some(120),
+            copyright: Some("© Me".to_string()),
+            image: Some("https://site/logo.png".to_string()),
+            ..ChannelInfo::default()
+        };
+        update.update(&mut feed).unwrap();
+
+        let info = ChannelInfo::read(&feed);
+        assert_eq!(info.ttl, Some(120));
+        assert_eq!(info.copyright.as_deref(), Some("© Me"));
+        assert_eq!(info.image.as_deref(), Some("https://site/logo.png"));
+        assert!(feed.content().contains("<title>My &amp; Blog</title>\n            <link>https://site/blog</link>\n        </image>"));
+        assert!(feed.content().contains("<item><title>Item</title><link>https://site/blog/a.html</link></item>"));
+        assert_eq!(feed.content().matches("<ttl>").count(), 1);
+    }
+}
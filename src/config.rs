@@ -0,0 +1,95 @@
+//! Settings read from a TOML config file
+
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+
+use crate::error::{Error, Result};
+use crate::fetch::FetchOptions;
+use crate::links::LinkRules;
+
+/// The config file used when none is given, if it exists
+pub const DEFAULT_CONFIG_FILE: &str = "htmltorss.toml";
+
+/// Settings from the config file, e.g.
+///
+/// ```toml
+/// [links]
+/// map = [{ local = "public", url = "https://site.com/" }]
+/// strip_extension = true
+/// index_as_directory = true
+///
+/// [fetch]
+/// timeout_secs = 10
+/// retries = 3
+/// headers = { "X-Preview" = "yes" }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Rules for computing item links from local paths
+    pub links: LinkRules,
+    /// Settings for fetching remote pages and files
+    pub fetch: FetchOptions,
+}
+
+impl Config {
+    /// Read the config file at `path`
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)?;
+        let config: Config = toml::from_str(&text).map_err(|e| Error::Config(format!("{}: {}", path.display(), e)))?;
+
+        // Catch bad HTTP settings now rather than quietly not sending them
+        config.fetch.check().map_err(|e| match e {
+            Error::Parse(message) => Error::Config(format!("{}: {}", path.display(), message)),
+            e => e,
+        })?;
+        Ok(config)
+    }
+
+    /// Read the given config file, else the default one if it exists,
+    /// else use the default settings
+    pub fn load_or_default(path: Option<&str>) -> Result<Self> {
+        match path {
+            Some(path) => Config::load(path),
+            None if Path::new(DEFAULT_CONFIG_FILE).is_file() => Config::load(DEFAULT_CONFIG_FILE),
+            None => Ok(Config::default()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn invalid_headers_and_user_agent() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        std::fs::write(&path, "[fetch]\nheaders = { \"Bad Name\" = \"x\" }\n").unwrap();
+        let bad_header = Config::load(&path);
+        std::fs::write(&path, "[fetch]\nuser_agent = \"line\\nbreak\"\n").unwrap();
+        let bad_user_agent = Config::load(&path);
+
+        assert!(matches!(bad_header, Err(Error::Config(message)) if message.contains("invalid header")));
+        assert!(matches!(bad_user_agent, Err(Error::Config(message)) if message.contains("invalid user agent")));
+    }
+
+    #[test]
+    fn parse_link_rules() {
+        let config: Config = toml::from_str(r#"
+            [links]
+            map = [{ local = "public", url = "https://site.com/" }]
+            strip_extension = true
+        "#).unwrap();
+        assert_eq!(config.links.map[0].url, "https://site.com/");
+        assert!(config.links.strip_extension);
+        assert!(!config.links.index_as_directory);
+    }
+
+    #[test]
+    fn unknown_settings_are_rejected() {
+        assert!(toml::from_str::<Config>("[links]\nstrip_extensions = true").is_err());
+    }
+}
@@ -0,0 +1,366 @@
+//! Extraction of RSS item content from HTML pages
+
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+
+use crate::error::{Error, Result};
+use crate::media::MediaImage;
+use crate::readability::{self, AUTO_SELECTOR};
+use crate::utils;
+use crate::xpath;
+
+/// The extracted content of an HTML page
+#[derive(Debug, Clone)]
+pub struct ProcessedContent {
+    /// Text for the item <title> element (unescaped)
+    pub title: String,
+    /// HTML for the item <description> element
+    pub description: String,
+    /// Number of words in the description text
+    pub word_count: usize,
+    /// Relative URLs which were converted to absolute URLs
+    pub rewritten_urls: Vec<String>,
+    /// The selector the content was taken from
+    pub selector: String,
+}
+
+/// Which part of a page becomes the item, and how it is tidied up
+#[derive(Debug, Clone, PartialEq)]
+pub struct ContentOptions {
+    /// CSS selectors tried in turn until one matches ("auto" finds the
+    /// main content by itself)
+    pub selectors: Vec<String>,
+    /// An XPath expression used instead of the selectors
+    pub xpath: Option<String>,
+    /// Join every element matched, not just the first
+    pub all_matches: bool,
+    /// The item title, instead of the text of the first `<h1>`
+    pub title: Option<String>,
+    /// Lines to cut from the start of the content
+    pub lines_to_cut: usize,
+}
+
+impl Default for ContentOptions {
+    fn default() -> Self {
+        ContentOptions {
+            selectors: vec!["main".to_string()],
+            xpath: None,
+            all_matches: false,
+            title: None,
+            lines_to_cut: 0,
+        }
+    }
+}
+
+/// Find the URL of the first `<audio>` or `<video>` in some HTML content,
+/// either from its `src` attribute or from its first `<source>` element
+pub fn first_media_source(html_content: &str) -> Option<String> {
+    let fragment = Html::parse_fragment(html_content);
+    let media = Selector::parse("audio[src], audio source[src], video[src], video source[src]").ok()?;
+    fragment.select(&media)
+        .find_map(|e| e.value().attr("src"))
+        .map(str::to_string)
+}
+
+/// Find the first `<img>` in some HTML content, with its size if the
+/// markup gives one
+pub fn first_image(html_content: &str) -> Option<MediaImage> {
+    let fragment = Html::parse_fragment(html_content);
+    let img = Selector::parse("img[src]").ok()?;
+    fragment.select(&img).next().map(|e| {
+        let attr = |name| e.value().attr(name);
+        MediaImage::new(attr("src").unwrap_or_default()).with_size(attr("width"), attr("height"))
+    })
+}
+
+/// The first of some matching elements, or all of them in page order
+/// (leaving out matches inside other matches as they're already included)
+fn matching_elements<'a>(matches: impl IntoIterator<Item = ElementRef<'a>>, all_matches: bool) -> Vec<ElementRef<'a>> {
+    let mut elements: Vec<ElementRef> = Vec::new();
+    for element in matches {
+        if !element.ancestors().any(|a| elements.iter().any(|e| e.id() == a.id())) {
+            elements.push(element);
+        }
+        if !all_matches {
+            break;
+        }
+    }
+    elements
+}
+
+/// The first of `selectors` found in the page, with its matching elements
+fn select_by_css<'a, 's>(document: &'a Html, selectors: &'s [String], all_matches: bool) -> Result<(&'s str, Vec<ElementRef<'a>>)> {
+    // Check all the selectors up front so a typo in a fallback isn't missed
+    let parsed = selectors.iter()
+        .map(|selector| match selector.as_str() {
+            AUTO_SELECTOR => Ok(None),
+            css => Selector::parse(css)
+                .map(Some)
+                .map_err(|_| Error::Parse(format!("invalid CSS selector '{}'", css))),
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    // Use the first selector that matches
+    selectors.iter()
+        .zip(&parsed)
+        .map(|(selector, parsed)| match parsed {
+            Some(parsed) => (selector.as_str(), matching_elements(document.select(parsed), all_matches)),
+            None => (selector.as_str(), readability::main_content(document).into_iter().collect()),
+        })
+        .find(|(_, elements)| !elements.is_empty())
+        .ok_or_else(|| Error::SelectorNotFound(selectors.join("' or '")))
+}
+
+/// Process HTML content and convert it to RSS item format
+///
+/// Takes the inner HTML of the page content picked out by `options`, one
+/// of:
+///
+/// * the first element matching the first of the selectors found in the
+///   page, so later ones act as fallbacks
+/// * "auto" as a selector, the main content as found by [`readability`]
+/// * an `xpath` expression, used instead of the selectors
+///
+/// With `all_matches` every matching element is used, joined in page
+/// order. The first `lines_to_cut` lines and any formatting whitespace are
+/// removed, and relative `src`, `href` and `srcset` URLs are made absolute
+/// using `base_url`. The title defaults to the text of the first `<h1>`.
+pub fn process_html_content(html_content: &str, base_url: &str, options: &ContentOptions) -> Result<ProcessedContent> {
+    let document = Html::parse_document(html_content);
+
+    // Use the XPath expression, else the selectors
+    let (selector, elements) = match &options.xpath {
+        Some(xpath) => (xpath.as_str(), matching_elements(xpath::select(&document, xpath)?, options.all_matches)),
+        None => select_by_css(&document, &options.selectors, options.all_matches)?,
+    };
+    if elements.is_empty() {
+        return Err(Error::SelectorNotFound(selector.to_string()));
+    }
+
+    // Get the inner HTML content
+    let mut html_content = elements.iter()
+        .map(|e| e.inner_html())
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    // Cut lines if specified
+    if options.lines_to_cut > 0 {
+        let mut lines: Vec<&str> = html_content.lines().collect();
+        if options.lines_to_cut < lines.len() {
+            lines.drain(..options.lines_to_cut);
+            html_content = lines.join("\n");
+        }
+    }
+
+    // Clean up whitespace
+    let re_whitespace = Regex::new(r"\s+")?;
+    html_content = re_whitespace.replace_all(&html_content, " ").to_string();
+
+    // Extract title from first h1 if not provided as an arg
+    let item_title = match &options.title {
+        Some(t) => t.to_string(),
+        None => {
+            // Find first h1 element
+            let h1_selector = Selector::parse("h1").map_err(|_| Error::Parse("invalid H1 selector".to_string()))?;
+            if let Some(h1_element) = document.select(&h1_selector).next() {
+                h1_element.text().collect::<Vec<_>>().join(" ")
+            } else {
+                "Untitled".to_string()
+            }
+        }
+    };
+
+    // Convert any relative URLs to absolute, keeping a note of each one
+    let mut processed_html = html_content;
+    let mut rewritten_urls = Vec::new();
+
+    // Process src, href and srcset attributes
+    let re_src = Regex::new(r#"src\s*=\s*"([^"]*)""#)?;
+    let re_href = Regex::new(r#"href\s*=\s*"([^"]*)""#)?;
+    let re_srcset = Regex::new(r#"srcset\s*=\s*"([^"]*)""#)?;
+
+    // Make a URL absolute, noting it, or the first URL which can't be
+    // (NB: the regex replacement closures can't return errors themselves)
+    let mut url_error = None;
+    let mut absolute = |url: &str| match utils::merge_url_and_fragment(base_url, url) {
+        Ok(absolute_url) => {
+            rewritten_urls.push(absolute_url.clone());
+            absolute_url
+        }
+        Err(e) => {
+            url_error.get_or_insert(e);
+            url.to_string()
+        }
+    };
+
+    // Process src attributes
+    processed_html = re_src.replace_all(&processed_html, |caps: &regex::Captures| {
+        let attr_value = &caps[1];
+        if !attr_value.starts_with("http") {
+            format!("src=\"{}\"", absolute(attr_value))
+        } else {
+            caps[0].to_string()
+        }
+    }).to_string();
+
+    // Process href attributes
+    processed_html = re_href.replace_all(&processed_html, |caps: &regex::Captures| {
+        let attr_value = &caps[1];
+        if !attr_value.starts_with("http") {
+            format!("href=\"{}\"", absolute(attr_value))
+        } else {
+            caps[0].to_string()
+        }
+    }).to_string();
+
+    // Process srcset attributes
+    processed_html = re_srcset.replace_all(&processed_html, |caps: &regex::Captures| {
+        let attr_value = &caps[1];
+        // Handle multiple URLs in srcset, each with an optional
+        // width or density descriptor e.g. "pic-2x.png 2x"
+        let candidates: Vec<&str> = attr_value.split(',').map(|s| s.trim()).collect();
+        let processed_candidates: Vec<String> = candidates.iter().map(|candidate| {
+            let (url, descriptor) = candidate.split_once(char::is_whitespace).unwrap_or((candidate, ""));
+            let url = match url.starts_with("http") {
+                true => url.to_string(),
+                false => absolute(url),
+            };
+            match descriptor.trim() {
+                "" => url,
+                descriptor => format!("{} {}", url, descriptor),
+            }
+        }).collect();
+
+        format!("srcset=\"{}\"", processed_candidates.join(", "))
+    }).to_string();
+
+    if let Some(e) = url_error {
+        return Err(e.into());
+    }
+
+    // Count the words in the text of the final content
+    let word_count = Html::parse_fragment(&processed_html)
+        .root_element()
+        .text()
+        .flat_map(|t| t.split_whitespace())
+        .count();
+
+    Ok(ProcessedContent {
+        title: item_title,
+        description: processed_html,
+        word_count,
+        rewritten_urls,
+        selector: selector.to_string(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><body><main>
+        <h1>My   Title</h1>
+        <p>Some <a href="other.html">text</a></p>
+        <img src="/images/pic.png">
+    </main></body></html>"#;
+
+    /// Options using just the given selectors
+    fn selecting(selectors: &[&str]) -> ContentOptions {
+        ContentOptions { selectors: selectors.iter().map(|s| s.to_string()).collect(), ..ContentOptions::default() }
+    }
+
+    #[test]
+    fn srcset_candidates_rewritten_separately() {
+        let page = r#"<main><img srcset="a.png 1x, https://cdn/b.png 2x, c.png"></main>"#;
+        let content = process_html_content(page, "https://site/blog", &ContentOptions::default()).unwrap();
+        assert!(content.description.contains(r#"srcset="https://site/blog/a.png 1x, https://cdn/b.png 2x, https://site/blog/c.png""#));
+        assert_eq!(content.rewritten_urls, ["https://site/blog/a.png", "https://site/blog/c.png"]);
+    }
+
+    #[test]
+    fn bad_base_url_is_an_error() {
+        let result = process_html_content(PAGE, "not a url", &ContentOptions::default());
+        assert!(matches!(result, Err(Error::Url(_))));
+    }
+
+    #[test]
+    fn extracts_title_and_rewrites_urls() {
+        let content = process_html_content(PAGE, "https://site/blog", &ContentOptions::default()).unwrap();
+        assert_eq!(content.title, "My   Title");
+        assert!(content.description.contains(r#"href="https://site/blog/other.html""#));
+        assert!(content.description.contains(r#"src="https://site/images/pic.png""#));
+        assert_eq!(content.rewritten_urls.len(), 2);
+        assert_eq!(content.word_count, 4);
+    }
+
+    #[test]
+    fn title_override_and_lines_to_cut() {
+        let content = process_html_content(PAGE, "https://site/blog", &ContentOptions { title: Some("Other".to_string()), lines_to_cut: 2, ..ContentOptions::default() }).unwrap();
+        assert_eq!(content.title, "Other");
+        assert!(!content.description.contains("<h1>"));
+    }
+
+    #[test]
+    fn media_source_in_document_order() {
+        let html = r#"<p>Listen</p><video><source src="a.mp4"></video><audio src="b.mp3"></audio>"#;
+        assert_eq!(first_media_source(html).as_deref(), Some("a.mp4"));
+        assert_eq!(first_media_source("<p>No media</p>"), None);
+    }
+
+    #[test]
+    fn first_image_with_size() {
+        let html = r#"<p>Hi</p><img src="https://site/a.png" width="300" height="200"><img src="b.png">"#;
+        let image = first_image(html).unwrap();
+        assert_eq!((image.url.as_str(), image.width, image.height), ("https://site/a.png", Some(300), Some(200)));
+        assert_eq!(first_image("<p>No images</p>"), None);
+    }
+
+    #[test]
+    fn missing_selector_is_an_error() {
+        let result = process_html_content(PAGE, "https://site/blog", &selecting(&["article", ".post"]));
+        assert_eq!(result.unwrap_err().to_string(), "selector 'article' or '.post' not found in HTML");
+    }
+
+    #[test]
+    fn concatenates_all_matches() {
+        let page = r#"<main>
+            <section class="post-body"><p>One</p><section class="post-body"><p>Nested</p></section></section>
+            <aside>Ad</aside>
+            <section class="post-body"><p>Two</p></section>
+        </main>"#;
+        let first = process_html_content(page, "https://site/", &selecting(&[".post-body"])).unwrap();
+        let all = process_html_content(page, "https://site/", &ContentOptions { all_matches: true, ..selecting(&[".post-body"]) }).unwrap();
+        assert_eq!(first.word_count, 2);
+        assert_eq!(all.description, r#"<p>One</p><section class="post-body"><p>Nested</p></section> <p>Two</p>"#);
+    }
+
+    #[test]
+    fn xpath_instead_of_selectors() {
+        let content = process_html_content(PAGE, "https://site/blog", &ContentOptions { xpath: Some("//main/p".to_string()), ..selecting(&["article"]) }).unwrap();
+        assert_eq!(content.selector, "//main/p");
+        assert_eq!(content.word_count, 2);
+
+        let result = process_html_content(PAGE, "https://site/blog", &ContentOptions { xpath: Some("//article".to_string()), ..ContentOptions::default() });
+        assert!(matches!(result, Err(Error::SelectorNotFound(_))));
+    }
+
+    #[test]
+    fn auto_selector() {
+        let page = r#"<html><body><nav><a href="/">Home</a></nav><div class="post">
+            <p>A paragraph which is long enough to be taken as the content of the page.</p>
+        </div></body></html>"#;
+        let content = process_html_content(page, "https://site/", &selecting(&["main", "auto"])).unwrap();
+        assert_eq!(content.selector, "auto");
+        assert!(content.description.starts_with(" <p>A paragraph"));
+    }
+
+    #[test]
+    fn falls_back_to_later_selectors() {
+        let content = process_html_content(PAGE, "https://site/blog", &selecting(&["article", "main", "body"])).unwrap();
+        assert_eq!(content.selector, "main");
+        assert!(content.description.starts_with(" <h1>"));
+
+        let result = process_html_content(PAGE, "https://site/blog", &selecting(&["main", "p["]));
+        assert!(matches!(result, Err(Error::Parse(_))));
+    }
+}
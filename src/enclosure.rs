@@ -0,0 +1,170 @@
+//! Media enclosures (audio, video and downloadable files) for RSS items
+
+use reqwest::header::{CONTENT_LENGTH, CONTENT_TYPE};
+use reqwest::Method;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::error::Result;
+use crate::fetch::{local_path, FetchOptions};
+use crate::links::LinkRules;
+use crate::utils;
+
+/// An item <enclosure> describing an attached media file
+#[derive(Debug, Clone, PartialEq)]
+pub struct Enclosure {
+    /// Public URL of the file
+    pub url: String,
+    /// Size of the file in bytes
+    pub length: u64,
+    /// MIME type of the file, e.g. "audio/mpeg"
+    pub mime_type: String,
+    /// Local copy of the file, if there is one
+    pub path: Option<PathBuf>,
+}
+
+impl Enclosure {
+    /// Create an enclosure from a local file, served at the given `url`.
+    /// The length and MIME type come from the file itself.
+    pub fn from_file(path: impl AsRef<Path>, url: &str) -> Result<Self> {
+        let path = path.as_ref();
+        Ok(Enclosure {
+            url: url.to_string(),
+            length: fs::metadata(path)?.len(),
+            mime_type: guess_mime_type(&path.to_string_lossy()),
+            path: Some(path.to_path_buf()),
+        })
+    }
+
+    /// Create an enclosure for a remote file, using a HEAD request
+    /// to find its length and MIME type. In offline mode there is no
+    /// request, so the length is given as 0 (meaning unknown) and the
+    /// MIME type is guessed from the URL.
+    pub fn from_url(url: &str, options: &FetchOptions) -> Result<Self> {
+        if options.offline {
+            return Ok(Enclosure { url: url.to_string(), length: 0, mime_type: guess_mime_type(url), path: None });
+        }
+        let response = options.send(Method::HEAD, url)?;
+
+        // Read the headers directly as the body of a HEAD response is always empty
+        let header = |name| response.headers().get(name).and_then(|v| v.to_str().ok());
+        let length = header(CONTENT_LENGTH)
+            .and_then(|v| v.trim().parse().ok())
+            .unwrap_or(0);
+        let mime_type = header(CONTENT_TYPE)
+            .map(|v| v.split(';').next().unwrap_or(v).trim().to_string())
+            .unwrap_or_else(|| guess_mime_type(url));
+
+        Ok(Enclosure { url: url.to_string(), length, mime_type, path: None })
+    }
+
+    /// Create an enclosure from a local file path or URL.
+    ///
+    /// Local paths are converted to URLs in the same way as the item link,
+    /// using `link_rules` (but without its clean URL rules). URLs below
+    /// `base_url` are looked for alongside a local `html_path` first, so
+    /// that files which have not been uploaded yet can be used.
+    pub fn from_source(source: &str, base_url: &str, html_path: &str, link_rules: &LinkRules, options: &FetchOptions) -> Result<Self> {
+        if let Some(path) = local_path(source)? {
+            let url = link_rules.public_url(base_url, source)?;
+            return Self::from_file(path, &url);
+        }
+
+        if let Some(local) = local_counterpart(source, base_url, html_path) {
+            return Self::from_file(local, source);
+        }
+        Self::from_url(source, options)
+    }
+
+    /// The <enclosure> element
+    pub fn to_xml(&self) -> String {
+        format!(r#"<enclosure url="{}" length="{}" type="{}" />"#,
+            utils::escape_xml(&self.url),
+            self.length,
+            utils::escape_xml(&self.mime_type)
+        )
+    }
+}
+
+/// Find the local copy of a URL below `base_url`, which is taken to be
+/// the directory holding the local HTML file
+fn local_counterpart(url: &str, base_url: &str, html_path: &str) -> Option<PathBuf> {
+    let html_path = local_path(html_path).ok()??;
+    let base = base_url.trim_end_matches('/');
+    let relative = url.strip_prefix(base)?.strip_prefix('/')?;
+    let local = html_path.parent()?.join(relative);
+    local.is_file().then_some(local)
+}
+
+/// Guess a MIME type from a file name or URL
+fn guess_mime_type(name: &str) -> String {
+    // Ignore any query string or fragment on URLs
+    let name = name.split(['?', '#']).next().unwrap_or(name);
+    mime_guess::from_path(name)
+        .first_or_octet_stream()
+        .essence_str()
+        .to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offline_url_without_request() {
+        let options = FetchOptions { offline: true, ..FetchOptions::default() };
+        let enclosure = Enclosure::from_url("https://example.invalid/episode.mp3?v=1", &options).unwrap();
+        assert_eq!((enclosure.length, enclosure.mime_type.as_str()), (0, "audio/mpeg"));
+    }
+
+    #[test]
+    fn enclosure_from_local_file() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("audio")).unwrap();
+        fs::write(dir.path().join("audio/episode 1.mp3"), [0u8; 1234]).unwrap();
+        let html_path = dir.path().join("post.html");
+
+        // A URL below the base URL is found next to the HTML file
+        let enclosure = Enclosure::from_source(
+            "https://site/blog/audio/episode 1.mp3",
+            "https://site/blog/",
+            html_path.to_str().unwrap(),
+            &LinkRules::default(),
+            &FetchOptions::default(),
+        ).unwrap();
+
+        assert_eq!(enclosure.length, 1234);
+        assert_eq!(enclosure.mime_type, "audio/mpeg");
+        assert_eq!(enclosure.to_xml(), r#"<enclosure url="https://site/blog/audio/episode 1.mp3" length="1234" type="audio/mpeg" />"#);
+    }
+
+    #[test]
+    fn local_file_url_from_mapping() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::create_dir_all(dir.path().join("public/audio")).unwrap();
+        fs::write(dir.path().join("public/audio/ep.mp3"), [0u8; 10]).unwrap();
+        let public = dir.path().join("public").to_string_lossy().into_owned();
+        let rules = LinkRules { map: vec![format!("{}=https://site.com/", public).parse().unwrap()], ..LinkRules::default() };
+
+        let source = format!("{}/audio/ep.mp3", public);
+        let enclosure = Enclosure::from_source(&source, "https://site.com/blog", &format!("{}/post.html", public), &rules, &FetchOptions::default()).unwrap();
+        assert_eq!(enclosure.url, "https://site.com/audio/ep.mp3");
+    }
+
+    #[test]
+    fn enclosure_from_head_request() {
+        let server = crate::fetch::test_server::serve(vec![
+            ("200 OK", "Content-Type: audio/mpeg; charset=binary\r\n", b""),
+        ]);
+        let url = format!("{}/episode.mp3", server.url);
+        let enclosure = Enclosure::from_url(&url, &FetchOptions::default()).unwrap();
+        assert_eq!(enclosure.mime_type, "audio/mpeg");
+        assert!(server.requests.lock().unwrap()[0].starts_with("HEAD /episode.mp3 "));
+    }
+
+    #[test]
+    fn mime_type_ignores_query_string() {
+        assert_eq!(guess_mime_type("https://site/v.mp4?x=1"), "video/mp4");
+        assert_eq!(guess_mime_type("file.unknownext"), "application/octet-stream");
+    }
+}
@@ -0,0 +1,109 @@
+//! Character encoding detection and transcoding of HTML to UTF-8
+
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use regex::bytes::Regex;
+
+use crate::error::{Error, Result};
+
+/// How far into a page to look for a <meta> charset declaration
+const META_SCAN_BYTES: usize = 4096;
+
+/// Decode an HTML page to UTF-8.
+///
+/// The encoding is taken from, in order of preference: the `override_label`
+/// (e.g. from `--encoding`), a byte order mark, the charset in an HTTP
+/// `content_type` header and a `<meta>` charset declaration. Failing those,
+/// pages which aren't valid UTF-8 are assumed to be Windows-1252, which is a
+/// superset of Latin-1 and what browsers assume.
+pub fn decode_html(bytes: &[u8], content_type: Option<&str>, override_label: Option<&str>) -> Result<String> {
+    let encoding = match override_label {
+        Some(label) => Some(encoding_for_label(label)?),
+        None => Encoding::for_bom(bytes).map(|(encoding, _)| encoding)
+            .or_else(|| content_type.and_then(charset_from_content_type))
+            .or_else(|| charset_from_meta(bytes)),
+    };
+
+    let encoding = encoding.unwrap_or(match std::str::from_utf8(bytes) {
+        Ok(_) => UTF_8,
+        Err(_) => WINDOWS_1252,
+    });
+
+    // (NB: decode() removes any BOM and replaces malformed sequences)
+    let (text, _, _) = encoding.decode(bytes);
+    Ok(text.into_owned())
+}
+
+/// Look up an encoding by name, e.g. "latin1" or "windows-1252"
+pub fn encoding_for_label(label: &str) -> Result<&'static Encoding> {
+    Encoding::for_label(label.trim().as_bytes())
+        .ok_or_else(|| Error::Parse(format!("unknown character encoding '{}'", label)))
+}
+
+/// The charset parameter of a Content-Type header value
+fn charset_from_content_type(content_type: &str) -> Option<&'static Encoding> {
+    content_type.split(';')
+        .skip(1)
+        .filter_map(|param| param.split_once('='))
+        .find(|(name, _)| name.trim().eq_ignore_ascii_case("charset"))
+        .and_then(|(_, value)| Encoding::for_label(value.trim().trim_matches(['"', '\'']).as_bytes()))
+}
+
+/// The charset from `<meta charset="...">` or
+/// `<meta http-equiv="Content-Type" content="text/html; charset=...">`
+fn charset_from_meta(bytes: &[u8]) -> Option<&'static Encoding> {
+    let head = &bytes[..bytes.len().min(META_SCAN_BYTES)];
+    let re = Regex::new(r#"(?i)<meta[^>]+charset\s*=\s*["']?([a-z0-9_:.\-]+)"#).ok()?;
+    let label = re.captures(head)?.get(1)?.as_bytes();
+    let encoding = Encoding::for_label(label)?;
+
+    // A page can't really be in UTF-16 if its <meta> could be read as ASCII
+    // so, as browsers do, use UTF-8 instead
+    match encoding == UTF_16LE || encoding == UTF_16BE {
+        true => Some(UTF_8),
+        false => Some(encoding),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // "café" in Windows-1252
+    const LATIN1: &[u8] = b"<p>caf\xe9</p>";
+
+    #[test]
+    fn utf8_by_default() {
+        assert_eq!(decode_html("<p>café</p>".as_bytes(), None, None).unwrap(), "<p>café</p>");
+    }
+
+    #[test]
+    fn invalid_utf8_falls_back_to_windows_1252() {
+        assert_eq!(decode_html(LATIN1, None, None).unwrap(), "<p>café</p>");
+    }
+
+    #[test]
+    fn bom_wins_over_content_type() {
+        let bytes = [&[0xEF, 0xBB, 0xBF][..], "é".as_bytes()].concat();
+        assert_eq!(decode_html(&bytes, Some("text/html; charset=iso-8859-1"), None).unwrap(), "é");
+    }
+
+    #[test]
+    fn content_type_charset() {
+        // In KOI8-R 0xC1 is "а"
+        assert_eq!(decode_html(b"\xc1", Some("text/html; charset=\"KOI8-R\""), None).unwrap(), "а");
+    }
+
+    #[test]
+    fn meta_charset() {
+        let html = b"<html><head><meta http-equiv=\"Content-Type\" content=\"text/html; charset=iso-8859-15\"></head><p>\xa4</p>";
+        assert!(decode_html(html, None, None).unwrap().ends_with("<p>€</p>"));
+        let html = b"<meta charset='windows-1251'><p>\xe0</p>";
+        assert!(decode_html(html, None, None).unwrap().ends_with("<p>а</p>"));
+    }
+
+    #[test]
+    fn override_wins() {
+        assert_eq!(decode_html("é".as_bytes(), None, Some("latin1")).unwrap(), "Ã©");
+        assert!(decode_html(b"x", None, Some("klingon")).is_err());
+    }
+}
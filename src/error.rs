@@ -0,0 +1,89 @@
+//! Error types for HTMLtoRSS
+//!
+//! Each kind of failure maps to its own process exit code so that
+//! scripts and CI pipelines can react to specific problems.
+
+use thiserror::Error;
+
+/// Everything that can go wrong while creating an RSS item
+#[derive(Debug, Error)]
+pub enum Error {
+    /// The HTML page could not be fetched from its URL
+    #[error("failed to fetch {url}: {source}")]
+    Fetch { url: String, source: reqwest::Error },
+
+    /// The CSS selector or other pattern could not be parsed
+    #[error("parse error: {0}")]
+    Parse(String),
+
+    /// The CSS selector did not match anything in the HTML
+    #[error("selector '{0}' not found in HTML")]
+    SelectorNotFound(String),
+
+    /// The supplied date could not be understood
+    #[error("invalid date '{input}': {source}")]
+    Date { input: String, source: chrono::ParseError },
+
+    /// A URL could not be parsed or joined
+    #[error("invalid URL: {0}")]
+    Url(#[from] url::ParseError),
+
+    /// The RSS file is not in the expected format
+    #[error("invalid RSS file: {0}")]
+    FeedFormat(String),
+
+    /// The config file is invalid
+    #[error("invalid config file {0}")]
+    Config(String),
+
+    /// An item link can't be made from where the page was read
+    #[error("can't make an item link for {0}, give the link or map its directory to a URL")]
+    NoLink(String),
+
+    /// The pages to read can't be read together
+    #[error("invalid pages: {0}")]
+    Sources(String),
+
+    /// A page was needed in offline mode but isn't in the cache
+    #[error("{0} is not in the cache and fetching is disabled by offline mode")]
+    Offline(String),
+
+    /// A file could not be read or written
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+}
+
+impl Error {
+    /// The process exit code for this kind of error
+    /// (NB: 1 is left for panics and 2 is used by clap for bad arguments)
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            Error::Io(_) => 3,
+            Error::Fetch { .. } => 4,
+            Error::Parse(_) => 5,
+            Error::SelectorNotFound(_) => 6,
+            Error::Date { .. } => 7,
+            Error::Url(_) => 8,
+            Error::FeedFormat(_) => 9,
+            Error::Config(_) => 10,
+            Error::Offline(_) => 11,
+            Error::NoLink(_) => 12,
+            Error::Sources(_) => 13,
+        }
+    }
+}
+
+impl From<regex::Error> for Error {
+    fn from(e: regex::Error) -> Self {
+        Error::Parse(e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for Error {
+    fn from(e: serde_json::Error) -> Self {
+        Error::Io(e.into())
+    }
+}
+
+/// Result type used throughout HTMLtoRSS
+pub type Result<T> = std::result::Result<T, Error>;
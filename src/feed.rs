@@ -0,0 +1,392 @@
+//! Loading, updating and saving RSS feed files
+
+use chrono::DateTime;
+use fs2::FileExt;
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+use crate::error::{Error, Result};
+use crate::item::RssItem;
+use crate::utils;
+
+/// New items are inserted at the end of the <channel> element
+const PLACE_BEFORE: &str = "</channel>";
+
+/// An RSS feed file held open under an exclusive advisory lock
+///
+/// The lock is taken by [`Feed::load`] and held until the feed is saved
+/// or dropped, so concurrent invocations serialise their
+/// read-modify-write of the same file.
+#[derive(Debug)]
+pub struct Feed {
+    path: PathBuf,
+    file: File,
+    original: String,
+    content: String,
+}
+
+impl Feed {
+    /// Open and lock the feed file at `path` and read its content
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+
+        // Open for reading and writing and block until we hold the lock
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open(&path)?;
+        file.lock_exclusive()?;
+
+        let mut content = String::new();
+        file.read_to_string(&mut content)?;
+
+        Ok(Feed { path, file, original: content.clone(), content })
+    }
+
+    /// The path of the feed file
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The current (possibly modified) XML of the feed
+    pub fn content(&self) -> &str {
+        &self.content
+    }
+
+    /// Whether the feed has been modified since it was loaded
+    pub fn is_modified(&self) -> bool {
+        self.content != self.original
+    }
+
+    /// Declare an XML namespace on the <rss> element if it isn't already
+    pub fn ensure_namespace(&mut self, prefix: &str, uri: &str) -> Result<()> {
+        let declaration = format!("xmlns:{}=", prefix);
+        let start = self.content.find("<rss")
+            .ok_or_else(|| Error::FeedFormat(format!("no <rss> element found in {}", self.path.display())))?;
+        let end = start + self.content[start..].find('>')
+            .ok_or_else(|| Error::FeedFormat(format!("unterminated <rss> element in {}", self.path.display())))?;
+
+        if !self.content[start..end].contains(&declaration) {
+            self.content.insert_str(end, &format!(r#" xmlns:{}="{}""#, prefix, uri));
+        }
+        Ok(())
+    }
+
+    /// The XML of a channel-level element, i.e. a direct child of <channel>
+    /// (not one inside an <item> or <image>)
+    pub fn channel_element(&self, tag: &str) -> Option<&str> {
+        let (start, end) = self.channel_header().ok()?;
+        let header = &self.content[start..end];
+        find_child_element(header, tag).map(|(el_start, el_end)| &header[el_start..el_end])
+    }
+
+    /// Set a channel-level element, e.g. `set_channel_element("ttl", "<ttl>60</ttl>")`.
+    ///
+    /// An existing direct child of <channel> with the same tag name before
+    /// the first <item> is replaced, otherwise the new element is added after the other channel
+    /// elements. Everything else in the file is left as it is.
+    pub fn set_channel_element(&mut self, tag: &str, xml: &str) -> Result<()> {
+        let (start, end) = self.channel_header()?;
+        if let Some((el_start, el_end)) = find_child_element(&self.content[start..end], tag) {
+            self.content.replace_range(start + el_start..start + el_end, xml);
+            return Ok(());
+        }
+
+        // Add on its own line, indented like the other channel elements,
+        // if the next element starts a line, else just before it (e.g.
+        // in a feed all on one line)
+        let line_start = self.content[..end].rfind('\n').map_or(0, |i| i + 1);
+        if self.content[line_start..end].trim().is_empty() {
+            self.content.insert_str(line_start, &format!("        {}\n", xml));
+        } else {
+            self.content.insert_str(end, xml);
+        }
+        Ok(())
+    }
+
+    /// The byte range of the channel's own elements, i.e. everything
+    /// between <channel> and the first <item> (or </channel>)
+    fn channel_header(&self) -> Result<(usize, usize)> {
+        let missing = |what: &str| Error::FeedFormat(format!("no {} element found in {}", what, self.path.display()));
+        let open = find_element_start(&self.content, "channel").ok_or_else(|| missing("<channel>"))?;
+        let start = open + self.content[open..].find('>').ok_or_else(|| missing("<channel>"))? + 1;
+        let end = find_child_element(&self.content[start..], "item")
+            .map(|(item_start, _)| item_start)
+            .or_else(|| self.content[start..].find(PLACE_BEFORE))
+            .map(|i| start + i)
+            .ok_or_else(|| missing(PLACE_BEFORE))?;
+        Ok((start, end))
+    }
+
+    /// Insert an item at the end of the feed's <channel> element,
+    /// declaring any namespaces the item uses
+    pub fn insert_item(&mut self, item: &RssItem) -> Result<()> {
+        for (prefix, uri) in item.namespaces() {
+            self.ensure_namespace(prefix, uri)?;
+        }
+        self.content = utils::insert_before(&self.content, PLACE_BEFORE, &item.to_xml())
+            .ok_or_else(|| Error::FeedFormat(format!("no {} element found in {}", PLACE_BEFORE, self.path.display())))?;
+        Ok(())
+    }
+
+    /// A unified diff between the feed as loaded and its current content
+    pub fn diff(&self) -> String {
+        utils::unified_diff(&self.original, &self.content, &self.path.display().to_string())
+    }
+
+    /// Set the channel's <lastBuildDate> (an RFC 2822 date)
+    pub fn set_last_build_date(&mut self, date: &str) -> Result<()> {
+        self.set_channel_element("lastBuildDate", &format!("<lastBuildDate>{}</lastBuildDate>", date))
+    }
+
+    /// The most recent <pubDate> of all the feed's items, in RFC 2822 format
+    pub fn newest_item_date(&self) -> Option<String> {
+        let mut rest = self.content.as_str();
+        let mut newest = None;
+        while let Some(start) = find_element_start(rest, "item") {
+            rest = &rest[start..];
+            let end = rest.find("</item>").map_or(rest.len(), |i| i + "</item>".len());
+            let date = find_element(&rest[..end], "pubDate")
+                .and_then(|(s, e)| {
+                    let element = &rest[s..e];
+                    let text = &element[element.find('>')? + 1..element.rfind("</")?];
+                    DateTime::parse_from_rfc2822(text.trim()).ok()
+                });
+            newest = newest.max(date);
+            rest = &rest[end..];
+        }
+        newest.map(|d| d.to_rfc2822())
+    }
+
+    /// Set the channel's <pubDate> to that of the newest item, if there are any
+    pub fn set_pub_date_to_newest_item(&mut self) -> Result<()> {
+        match self.newest_item_date() {
+            Some(date) => self.set_channel_element("pubDate", &format!("<pubDate>{}</pubDate>", date)),
+            None => Ok(()),
+        }
+    }
+
+    /// Write the feed back to its file and release the lock.
+    /// The channel's <lastBuildDate> is set to the current time so that
+    /// feed readers notice the change.
+    pub fn save(mut self) -> Result<()> {
+        self.set_last_build_date(&utils::now_rfc2822())?;
+
+        // Write back to the same (still locked) file handle
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.file.write_all(self.content.as_bytes())?;
+        self.file.sync_all()?;
+        Ok(())
+    }
+}
+
+/// Find the byte offset of the first `<tag` start tag (not a longer tag name)
+fn find_element_start(xml: &str, tag: &str) -> Option<usize> {
+    let open = format!("<{}", tag);
+    xml.match_indices(&open)
+        .map(|(i, _)| i)
+        .find(|&i| matches!(xml[i + open.len()..].chars().next(), Some(' ' | '>' | '/' | '\n' | '\r' | '\t')))
+}
+
+/// Find the byte range of the first `<tag>` element at the top level of
+/// an XML fragment, i.e. not nested inside another element. The scan
+/// stops at the end tag of the fragment's parent, if there is one.
+/// (NB: comments, CDATA sections and processing instructions are skipped)
+pub(crate) fn find_child_element(xml: &str, tag: &str) -> Option<(usize, usize)> {
+    let mut depth = 0;
+    let mut element_start = None;
+    let mut pos = 0;
+    while let Some(offset) = xml[pos..].find('<') {
+        let lt = pos + offset;
+        let rest = &xml[lt..];
+        let len = if rest.starts_with("<!--") {
+            rest.find("-->")? + "-->".len()
+        } else if rest.starts_with("<![CDATA[") {
+            rest.find("]]>")? + "]]>".len()
+        } else {
+            rest.find('>')? + 1
+        };
+        let markup = &rest[..len];
+        pos = lt + len;
+
+        if markup.starts_with("<!") || markup.starts_with("<?") {
+            continue;
+        }
+        if markup.starts_with("</") {
+            if depth == 0 {
+                // The end of the parent element
+                return None;
+            }
+            depth -= 1;
+            if depth == 0 {
+                if let Some(start) = element_start {
+                    return Some((start, pos));
+                }
+            }
+            continue;
+        }
+        let name = markup[1..].split(|c: char| c.is_whitespace() || c == '/' || c == '>').next()?;
+        let is_match = depth == 0 && name == tag;
+        if markup.ends_with("/>") {
+            if is_match {
+                return Some((lt, pos));
+            }
+        } else {
+            if is_match {
+                element_start = Some(lt);
+            }
+            depth += 1;
+        }
+    }
+    None
+}
+
+/// Find the byte range of the first complete `<tag>` element, either
+/// self-closing or up to its end tag
+pub(crate) fn find_element(xml: &str, tag: &str) -> Option<(usize, usize)> {
+    let start = find_element_start(xml, tag)?;
+    let gt = start + xml[start..].find('>')?;
+    if xml[..gt].ends_with('/') {
+        return Some((start, gt + 1));
+    }
+    let close = format!("</{}>", tag);
+    let end = gt + xml[gt..].find(&close)? + close.len();
+    Some((start, end))
+}
+
+/// Feed files for tests
+#[cfg(test)]
+pub(crate) mod test_feed {
+    use std::path::PathBuf;
+    use tempfile::TempDir;
+
+    /// A feed with nothing in its <channel>
+    pub const EMPTY_FEED: &str = "<rss>\n    <channel>\n    </channel>\n</rss>\n";
+
+    /// Write a feed file in a new temporary directory, which is deleted
+    /// along with the file when the returned `TempDir` is dropped
+    pub fn write_feed(content: &str) -> (TempDir, PathBuf) {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("rss.xml");
+        std::fs::write(&path, content).unwrap();
+        (dir, path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::test_feed::{write_feed, EMPTY_FEED};
+
+    #[test]
+    fn insert_and_save_item() {
+        let (_dir, path) = write_feed(EMPTY_FEED);
+        let mut feed = Feed::load(&path).unwrap();
+        assert!(!feed.is_modified());
+        feed.insert_item(&RssItem::new("A & B", "<p>Hi</p>", "https://site/a.html", "date")).unwrap();
+        assert!(feed.is_modified());
+        assert!(feed.diff().contains("+            <guid>https://site/a.html</guid>"));
+        feed.save().unwrap();
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.contains("<title>A &amp; B</title>"));
+        assert!(saved.ends_with("</item>\n    </channel>\n</rss>\n"));
+    }
+
+    #[test]
+    fn namespace_declared_once() {
+        let (_dir, path) = write_feed(EMPTY_FEED);
+        let mut feed = Feed::load(&path).unwrap();
+        let mut item = RssItem::new("T", "", "https://site/", "date");
+        item.author = Some("Jo".to_string());
+        feed.insert_item(&item).unwrap();
+        feed.insert_item(&item).unwrap();
+        assert!(feed.content().starts_with(r#"<rss xmlns:dc="http://purl.org/dc/elements/1.1/">"#));
+        assert_eq!(feed.content().matches("xmlns:dc").count(), 1);
+    }
+
+    #[test]
+    fn channel_elements_replaced_or_added() {
+        let (_dir, path) = write_feed("<rss>\n    <channel>\n        <title>Old</title>\n        <item><title>Item</title></item>\n    </channel>\n</rss>\n");
+        let mut feed = Feed::load(&path).unwrap();
+        feed.set_channel_element("title", "<title>New</title>").unwrap();
+        feed.set_channel_element("itunes:image", r#"<itunes:image href="a.png" />"#).unwrap();
+        feed.set_channel_element("itunes:image", r#"<itunes:image href="b.png" />"#).unwrap();
+        assert_eq!(feed.content(), "<rss>\n    <channel>\n        <title>New</title>\n        <itunes:image href=\"b.png\" />\n        <item><title>Item</title></item>\n    </channel>\n</rss>\n");
+    }
+
+    #[test]
+    fn channel_elements_are_direct_children() {
+        let (_dir, path) = write_feed("<rss>\n    <channel>\n        <image><title>Logo</title><link>https://s/</link></image>\n        <!-- <title>Commented</title> -->\n        <title>Blog</title>\n        <item><title>Item</title></item>\n    </channel>\n</rss>\n");
+        let mut feed = Feed::load(&path).unwrap();
+        assert_eq!(feed.channel_element("title"), Some("<title>Blog</title>"));
+        assert_eq!(feed.channel_element("link"), None);
+
+        feed.set_channel_element("title", "<title>New</title>").unwrap();
+        feed.set_channel_element("link", "<link>https://site/</link>").unwrap();
+        assert!(feed.content().contains("<image><title>Logo</title><link>https://s/</link></image>"));
+        assert!(feed.content().contains("        <title>New</title>\n        <link>https://site/</link>\n        <item>"));
+    }
+
+    #[test]
+    fn channel_elements_in_a_one_line_feed() {
+        let (_dir, path) = write_feed("<?xml version=\"1.0\"?>\n<rss version=\"2.0\"><channel><title>T</title></channel></rss>\n");
+        let mut feed = Feed::load(&path).unwrap();
+        feed.set_channel_element("ttl", "<ttl>5</ttl>").unwrap();
+        feed.insert_item(&RssItem::new("I", "", "https://site/", "Fri, 2 Jun 2023 14:30:00 +0000")).unwrap();
+        feed.set_last_build_date("Fri, 2 Jun 2023 14:30:00 +0000").unwrap();
+
+        let content = feed.content();
+        assert!(content.starts_with("<?xml version=\"1.0\"?>\n<rss version=\"2.0\"><channel><title>T</title><ttl>5</ttl>"));
+        assert!(content.find("<channel>") < content.find("<lastBuildDate>"));
+        assert!(content.find("</lastBuildDate>") < content.find("<item>"));
+    }
+
+    #[test]
+    fn build_and_pub_dates() {
+        let (_dir, path) = write_feed(EMPTY_FEED);
+        let mut feed = Feed::load(&path).unwrap();
+        for date in ["Fri, 2 Jun 2023 14:30:00 +0000", "Sat, 3 Jun 2023 09:00:00 +0100", "Thu, 1 Jun 2023 08:00:00 +0000"] {
+            feed.insert_item(&RssItem::new("T", "", "https://site/", date)).unwrap();
+        }
+        assert_eq!(feed.newest_item_date().as_deref(), Some("Sat, 3 Jun 2023 09:00:00 +0100"));
+        feed.set_pub_date_to_newest_item().unwrap();
+        feed.save().unwrap();
+
+        let saved = std::fs::read_to_string(&path).unwrap();
+        assert!(saved.starts_with("<rss>\n    <channel>\n        <pubDate>Sat, 3 Jun 2023 09:00:00 +0100</pubDate>\n        <lastBuildDate>"));
+        assert_eq!(saved.matches("<lastBuildDate>").count(), 1);
+    }
+
+    #[test]
+    fn concurrent_writers_keep_all_items() {
+        // Every concurrent insertion must survive, none may be lost
+        let (_dir, path) = write_feed(EMPTY_FEED);
+        let handles: Vec<_> = (0..8).map(|i| {
+            let path = path.clone();
+            std::thread::spawn(move || {
+                let mut feed = Feed::load(&path).unwrap();
+                feed.insert_item(&RssItem::new(&format!("Item {}", i), "", "https://site/", "date")).unwrap();
+                feed.save().unwrap();
+            })
+        }).collect();
+        for h in handles {
+            h.join().unwrap();
+        }
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        for i in 0..8 {
+            assert!(content.contains(&format!("<title>Item {}</title>", i)));
+        }
+    }
+
+    #[test]
+    fn insert_without_channel_is_an_error() {
+        let (_dir, path) = write_feed("<rss></rss>");
+        let mut feed = Feed::load(&path).unwrap();
+        let result = feed.insert_item(&RssItem::new("T", "", "https://site/", "date"));
+        assert!(matches!(result, Err(Error::FeedFormat(_))));
+    }
+}
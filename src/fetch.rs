@@ -0,0 +1,478 @@
+//! Reading HTML from local files, URLs and standard input
+
+use reqwest::blocking::{Client, Response};
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH, LAST_MODIFIED, USER_AGENT,
+};
+use reqwest::{Method, StatusCode};
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::{self, Read};
+use std::path::PathBuf;
+use std::thread;
+use std::time::Duration;
+
+use crate::auth::Auth;
+use crate::cache::{Cache, CachedResponse};
+use crate::encoding::decode_html;
+use crate::error::{Error, Result};
+
+/// The HTML source meaning "read from standard input"
+pub const STDIN: &str = "-";
+
+/// The User-Agent sent with every request unless another is given
+pub const DEFAULT_USER_AGENT: &str = concat!("HTMLtoRSS/", env!("CARGO_PKG_VERSION"), " (+https://github.com/bobosola/HTMLtoRSS)");
+
+/// Settings for fetching remote pages and files
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FetchOptions {
+    /// Time allowed for each request, in seconds
+    pub timeout_secs: u64,
+    /// Number of times to retry after a connection failure, timeout,
+    /// 429 or 5xx response
+    pub retries: u32,
+    /// Delay before the first retry, in milliseconds; doubled for each retry
+    pub retry_delay_ms: u64,
+    pub user_agent: String,
+    /// Extra request headers
+    pub headers: BTreeMap<String, String>,
+    /// Credentials for password-protected pages
+    pub auth: Auth,
+    /// Character encoding of HTML pages, overriding detection
+    pub encoding: Option<String>,
+    /// Directory to cache fetched pages in, so that unchanged pages
+    /// aren't downloaded again
+    pub cache_dir: Option<PathBuf>,
+    /// Only use cached pages and never touch the network
+    pub offline: bool,
+    /// Most pages to read at once when reading several
+    pub concurrency: usize,
+    /// Least time between starting requests to the same host when
+    /// reading several pages, in milliseconds
+    pub host_delay_ms: u64,
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        FetchOptions {
+            timeout_secs: 30,
+            retries: 2,
+            retry_delay_ms: 500,
+            user_agent: DEFAULT_USER_AGENT.to_string(),
+            headers: BTreeMap::new(),
+            auth: Auth::default(),
+            encoding: None,
+            cache_dir: None,
+            offline: false,
+            concurrency: 4,
+            host_delay_ms: 250,
+        }
+    }
+}
+
+impl FetchOptions {
+    /// Send a request, retrying with exponential backoff on transient
+    /// failures, and fail on any non-2xx response
+    pub fn send(&self, method: Method, url: &str) -> Result<Response> {
+        self.send_with_headers(method, url, HeaderMap::new())
+    }
+
+    /// Send a request like [`FetchOptions::send`] with extra headers for
+    /// just this request
+    fn send_with_headers(&self, method: Method, url: &str, headers: HeaderMap) -> Result<Response> {
+        self.ready_to_send(url)?;
+        let fetch_error = |source| Error::Fetch { url: url.to_string(), source };
+        let client = self.client().map_err(fetch_error)?;
+
+        let mut attempt = 0;
+        loop {
+            let result = client.request(method.clone(), url)
+                .headers(headers.clone())
+                .headers(self.auth.headers(url)?)
+                .send();
+            match self.retry_delay(result.as_ref().map(Response::status), attempt) {
+                Some(delay) => thread::sleep(delay),
+                None => return result.and_then(|r| r.error_for_status()).map_err(fetch_error),
+            }
+            attempt += 1;
+        }
+    }
+
+    /// Fail if requests can't be sent, i.e. in offline mode or with bad headers
+    pub(crate) fn ready_to_send(&self, url: &str) -> Result<()> {
+        if self.offline {
+            return Err(Error::Offline(url.to_string()));
+        }
+        self.check()
+    }
+
+    /// How long to wait before retrying a request which got `outcome`
+    /// (a response status or error), doubling each time, or None if it
+    /// shouldn't be retried
+    pub(crate) fn retry_delay(&self, outcome: std::result::Result<StatusCode, &reqwest::Error>, attempt: u32) -> Option<Duration> {
+        let retryable = match outcome {
+            Ok(status) => is_retryable_status(status),
+            Err(e) => e.is_connect() || e.is_timeout(),
+        };
+        (retryable && attempt < self.retries)
+            .then(|| Duration::from_millis(self.retry_delay_ms.saturating_mul(1 << attempt.min(16))))
+    }
+
+    /// Fetch an HTML page and decode it to UTF-8, taking the character
+    /// encoding from the HTTP Content-Type header if the page has no BOM.
+    ///
+    /// With a cache, a page fetched before is only downloaded again if
+    /// the server says it has changed, and in offline mode it is never
+    /// downloaded at all.
+    pub fn get_html(&self, url: &str) -> Result<String> {
+        let lookup = self.cache_lookup(url)?;
+        if self.offline {
+            return self.decode(&lookup.offline_page(url)?);
+        }
+        let response = self.send_with_headers(Method::GET, url, lookup.conditional_headers())?;
+        let (status, headers) = (response.status(), response.headers().clone());
+        let body = response.bytes()
+            .map_err(|source| Error::Fetch { url: url.to_string(), source })?
+            .to_vec();
+        self.decode(&lookup.page(url, status, &headers, body)?)
+    }
+
+    /// Look a page up in the cache, if there is one (without one, offline
+    /// mode has no pages at all)
+    pub(crate) fn cache_lookup(&self, url: &str) -> Result<CacheLookup> {
+        let cache = self.cache_dir.as_ref().map(Cache::new);
+        let cached = match &cache {
+            Some(cache) => cache.get(url)?,
+            None => None,
+        };
+        Ok(CacheLookup { cache, cached })
+    }
+
+    /// Decode a fetched page to UTF-8
+    pub(crate) fn decode(&self, response: &CachedResponse) -> Result<String> {
+        decode_html(&response.body, response.content_type.as_deref(), self.encoding.as_deref())
+    }
+
+    /// Check the extra headers and user agent can be sent, e.g. those
+    /// from a config file, so none are silently left out
+    pub fn check(&self) -> Result<()> {
+        for (name, value) in &self.headers {
+            parse_header(&format!("{}: {}", name, value))?;
+        }
+        HeaderValue::from_str(&self.user_agent)
+            .map_err(|_| Error::Parse(format!("invalid user agent '{}'", self.user_agent)))?;
+        Ok(())
+    }
+
+    /// Build a client with the timeout, user agent and extra headers
+    fn client(&self) -> reqwest::Result<Client> {
+        Client::builder()
+            .timeout(Duration::from_secs(self.timeout_secs))
+            .default_headers(self.default_headers())
+            .build()
+    }
+
+    /// Build an async client like [`FetchOptions::client`], for reading
+    /// many pages at once
+    pub(crate) fn async_client(&self) -> reqwest::Result<reqwest::Client> {
+        reqwest::Client::builder()
+            .timeout(Duration::from_secs(self.timeout_secs))
+            .default_headers(self.default_headers())
+            .build()
+    }
+
+    /// The user agent and extra headers sent with every request
+    fn default_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.headers {
+            // (NB: header names and values have been through check)
+            if let (Ok(name), Ok(value)) = (HeaderName::from_bytes(name.as_bytes()), HeaderValue::from_str(value)) {
+                headers.insert(name, value);
+            }
+        }
+        if let Ok(user_agent) = HeaderValue::from_str(&self.user_agent) {
+            headers.insert(USER_AGENT, user_agent);
+        }
+        headers
+    }
+}
+
+/// A page's entry in the cache, if caching is on
+pub(crate) struct CacheLookup {
+    cache: Option<Cache>,
+    cached: Option<CachedResponse>,
+}
+
+impl CacheLookup {
+    /// The cached page, which must be there in offline mode
+    pub(crate) fn offline_page(self, url: &str) -> Result<CachedResponse> {
+        self.cached.ok_or_else(|| Error::Offline(url.to_string()))
+    }
+
+    /// Headers asking the server to send the page only if it has changed
+    pub(crate) fn conditional_headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        if let Some(cached) = &self.cached {
+            if let Some(Ok(etag)) = cached.etag.as_deref().map(HeaderValue::from_str) {
+                headers.insert(IF_NONE_MATCH, etag);
+            }
+            if let Some(Ok(date)) = cached.last_modified.as_deref().map(HeaderValue::from_str) {
+                headers.insert(IF_MODIFIED_SINCE, date);
+            }
+        }
+        headers
+    }
+
+    /// The page from a response to a request with the conditional headers,
+    /// i.e. the cached copy if it hasn't changed else the new one, which
+    /// is cached for next time
+    pub(crate) fn page(self, url: &str, status: StatusCode, headers: &HeaderMap, body: Vec<u8>) -> Result<CachedResponse> {
+        if status == StatusCode::NOT_MODIFIED {
+            if let Some(cached) = self.cached {
+                return Ok(cached);
+            }
+        }
+
+        let header = |name: HeaderName| headers.get(name).and_then(|v| v.to_str().ok()).map(str::to_string);
+        let fetched = CachedResponse {
+            url: url.to_string(),
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+            content_type: header(CONTENT_TYPE),
+            body,
+        };
+        if let Some(cache) = &self.cache {
+            cache.put(&fetched)?;
+        }
+        Ok(fetched)
+    }
+}
+
+/// Parse a "Name: value" request header
+pub fn parse_header(header: &str) -> Result<(String, String)> {
+    let invalid = || Error::Parse(format!("invalid header '{}', expected 'Name: value'", header));
+    let (name, value) = header.split_once(':').ok_or_else(invalid)?;
+    let (name, value) = (name.trim(), value.trim());
+    HeaderName::from_bytes(name.as_bytes()).map_err(|_| invalid())?;
+    HeaderValue::from_str(value).map_err(|_| invalid())?;
+    Ok((name.to_string(), value.to_string()))
+}
+
+/// Responses worth retrying, i.e. rate limiting and server errors
+fn is_retryable_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// The local file a source refers to, either a plain path or a `file://`
+/// URL, or None for http(s) URLs and standard input
+pub fn local_path(source: &str) -> Result<Option<PathBuf>> {
+    if source == STDIN || source.starts_with("http://") || source.starts_with("https://") {
+        return Ok(None);
+    }
+    if source.starts_with("file://") {
+        let path = url::Url::parse(source)?
+            .to_file_path()
+            .map_err(|_| Error::Parse(format!("'{}' is not a local file URL", source)))?;
+        return Ok(Some(path));
+    }
+    Ok(Some(PathBuf::from(source)))
+}
+
+/// Get the content of an HTML page from a URL, a local file path
+/// (or `file://` URL), or standard input if the source is "-"
+pub fn read_html(source: &str) -> Result<String> {
+    read_html_with(source, &FetchOptions::default())
+}
+
+/// Get the content of an HTML page like [`read_html`], using the given
+/// settings for fetching URLs
+pub fn read_html_with(source: &str, options: &FetchOptions) -> Result<String> {
+    if source == STDIN {
+        // e.g. piped straight from a site generator
+        let mut bytes = Vec::new();
+        io::stdin().lock().read_to_end(&mut bytes)?;
+        return decode_html(&bytes, None, options.encoding.as_deref());
+    }
+    match local_path(source)? {
+        // Read the local file, which needn't be UTF-8
+        Some(path) => decode_html(&fs::read(path)?, None, options.encoding.as_deref()),
+        // It's a URL so fetch it
+        None => options.get_html(source),
+    }
+}
+
+/// A minimal HTTP server for tests, which serves a fixed list of
+/// responses in turn and records the requests it receives
+#[cfg(test)]
+pub(crate) mod test_server {
+    use std::io::{BufRead, BufReader, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+
+    pub struct TestServer {
+        pub url: String,
+        pub requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    /// Start serving `responses`, each a (status line, headers, body)
+    pub fn serve(responses: Vec<(&'static str, &'static str, &'static [u8])>) -> TestServer {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = Arc::clone(&requests);
+
+        thread::spawn(move || {
+            for (status, headers, body) in responses {
+                let Ok((mut stream, _)) = listener.accept() else { return };
+
+                // Read the request head, up to the blank line
+                let mut reader = BufReader::new(stream.try_clone().unwrap());
+                let mut request = String::new();
+                loop {
+                    let mut line = String::new();
+                    if reader.read_line(&mut line).unwrap_or(0) == 0 || line == "\r\n" {
+                        break;
+                    }
+                    request.push_str(&line);
+                }
+                recorded.lock().unwrap().push(request);
+
+                let head = format!("HTTP/1.1 {}\r\nContent-Length: {}\r\nConnection: close\r\n{}\r\n", status, body.len(), headers);
+                let _ = stream.write_all(head.as_bytes());
+                let _ = stream.write_all(body);
+            }
+        });
+
+        TestServer { url, requests }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::test_server::serve;
+    use super::*;
+
+    fn quick() -> FetchOptions {
+        FetchOptions { retry_delay_ms: 1, ..FetchOptions::default() }
+    }
+
+    #[test]
+    fn sends_user_agent_and_headers() {
+        let server = serve(vec![("200 OK", "", b"<p>Hi</p>")]);
+        let mut options = quick();
+        let (name, value) = parse_header("X-Preview: yes").unwrap();
+        options.headers.insert(name, value);
+
+        assert_eq!(read_html_with(&server.url, &options).unwrap(), "<p>Hi</p>");
+        let request = server.requests.lock().unwrap()[0].to_lowercase();
+        assert!(request.contains(&format!("user-agent: {}", DEFAULT_USER_AGENT.to_lowercase())));
+        assert!(request.contains("x-preview: yes"));
+    }
+
+    #[test]
+    fn sends_credentials() {
+        let server = serve(vec![("200 OK", "", b""), ("200 OK", "", b"")]);
+        let mut options = quick();
+        options.auth = Auth { username: Some("me".to_string()), password: Some("secret".to_string()), ..Auth::default() };
+        options.auth = options.auth.with_hosts_of(std::slice::from_ref(&server.url));
+        read_html_with(&server.url, &options).unwrap();
+        options.auth.token = Some("t0k3n".to_string());
+        read_html_with(&server.url, &options).unwrap();
+
+        let requests = server.requests.lock().unwrap();
+        assert!(requests[0].to_lowercase().contains("authorization: basic bwu6c2vjcmv0"));
+        assert!(requests[1].to_lowercase().contains("authorization: bearer t0k3n"));
+    }
+
+    #[test]
+    fn no_credentials_for_other_hosts() {
+        let server = serve(vec![("200 OK", "", b"")]);
+        let mut options = quick();
+        options.auth = Auth { token: Some("t0k3n".to_string()), hosts: vec!["staging.site.com".to_string()], ..Auth::default() };
+        options.send(Method::HEAD, &server.url).unwrap();
+        assert!(!server.requests.lock().unwrap()[0].to_lowercase().contains("authorization"));
+    }
+
+    #[test]
+    fn offline_needs_a_cache() {
+        let options = FetchOptions { offline: true, ..FetchOptions::default() };
+        assert!(matches!(read_html_with("https://example.invalid/", &options), Err(Error::Offline(_))));
+    }
+
+    #[test]
+    fn not_found_fails_without_retrying() {
+        let server = serve(vec![("404 Not Found", "", b"missing"), ("200 OK", "", b"ok")]);
+        let result = read_html_with(&server.url, &quick());
+        assert!(matches!(result, Err(Error::Fetch { .. })));
+        assert_eq!(server.requests.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn server_errors_are_retried() {
+        let server = serve(vec![("500 Internal Server Error", "", b""), ("503 Service Unavailable", "", b""), ("200 OK", "", b"ok")]);
+        assert_eq!(read_html_with(&server.url, &quick()).unwrap(), "ok");
+        assert_eq!(server.requests.lock().unwrap().len(), 3);
+    }
+
+    #[test]
+    fn gives_up_after_retries() {
+        let server = serve(vec![("500 Internal Server Error", "", b""), ("500 Internal Server Error", "", b"")]);
+        let options = FetchOptions { retries: 1, ..quick() };
+        assert!(matches!(read_html_with(&server.url, &options), Err(Error::Fetch { .. })));
+    }
+
+    #[test]
+    fn decodes_using_content_type_charset() {
+        let server = serve(vec![("200 OK", "Content-Type: text/html; charset=ISO-8859-1\r\n", b"caf\xe9")]);
+        assert_eq!(read_html_with(&server.url, &quick()).unwrap(), "café");
+    }
+
+    #[test]
+    fn reads_file_urls() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("page.html");
+        fs::write(&path, "<p>Local</p>").unwrap();
+        let url = url::Url::from_file_path(&path).unwrap().to_string();
+        let html = read_html(&url);
+
+        assert_eq!(html.unwrap(), "<p>Local</p>");
+        assert_eq!(local_path(&url).unwrap(), Some(path));
+        assert_eq!(local_path("posts/a.html").unwrap(), Some(PathBuf::from("posts/a.html")));
+        assert_eq!(local_path(STDIN).unwrap(), None);
+        assert_eq!(local_path("https://site/a.html").unwrap(), None);
+    }
+
+    #[test]
+    fn conditional_requests_use_cache() {
+        let server = serve(vec![
+            ("200 OK", "ETag: \"v1\"\r\nLast-Modified: Fri, 02 Jun 2023 14:30:00 GMT\r\n", b"<p>Cached</p>"),
+            ("304 Not Modified", "", b""),
+        ]);
+        let dir = tempfile::tempdir().unwrap();
+        let mut options = quick();
+        options.cache_dir = Some(dir.path().to_path_buf());
+
+        let first = read_html_with(&server.url, &options).unwrap();
+        let second = read_html_with(&server.url, &options).unwrap();
+        options.offline = true;
+        let offline = read_html_with(&server.url, &options).unwrap();
+        let missing = read_html_with("https://example.invalid/", &options);
+
+        assert_eq!((first.as_str(), second.as_str(), offline.as_str()), ("<p>Cached</p>", "<p>Cached</p>", "<p>Cached</p>"));
+        assert!(matches!(missing, Err(Error::Offline(_))));
+        let requests = server.requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let second_request = requests[1].to_lowercase();
+        assert!(second_request.contains("if-none-match: \"v1\""));
+        assert!(second_request.contains("if-modified-since: fri, 02 jun 2023 14:30:00 gmt"));
+    }
+
+    #[test]
+    fn invalid_headers() {
+        assert!(parse_header("no colon").is_err());
+        assert!(parse_header("Bad Name: x").is_err());
+    }
+}
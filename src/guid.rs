@@ -0,0 +1,136 @@
+//! Strategies for generating item <guid> values
+
+use chrono::DateTime;
+use sha2::{Digest, Sha256};
+use std::fmt;
+use std::str::FromStr;
+use url::Url;
+use uuid::Uuid;
+
+use crate::error::{Error, Result};
+
+/// How to generate an item's <guid>
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum GuidMode {
+    /// The item's link, as per the RSS spec
+    #[default]
+    Permalink,
+    /// A random UUID
+    Uuid4,
+    /// A UUID derived from the item's link
+    Uuid5,
+    /// A SHA-256 hash of the item's content
+    Hash,
+    /// An RFC 4151 tag: URI from the link's host and path and the item date
+    Tag,
+}
+
+impl GuidMode {
+    /// All the modes
+    pub fn variants() -> [GuidMode; 5] {
+        [GuidMode::Permalink, GuidMode::Uuid4, GuidMode::Uuid5, GuidMode::Hash, GuidMode::Tag]
+    }
+
+    /// The mode's name, e.g. for the `--guid-mode` option
+    pub fn name(self) -> &'static str {
+        match self {
+            GuidMode::Permalink => "permalink",
+            GuidMode::Uuid4 => "uuid4",
+            GuidMode::Uuid5 => "uuid5",
+            GuidMode::Hash => "hash",
+            GuidMode::Tag => "tag",
+        }
+    }
+}
+
+impl FromStr for GuidMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        GuidMode::variants()
+            .into_iter()
+            .find(|mode| mode.name() == s)
+            .ok_or_else(|| format!("unknown guid mode '{}' (expected permalink, uuid4, uuid5, hash or tag)", s))
+    }
+}
+
+impl fmt::Display for GuidMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Generate a guid for an item, returning the value and whether it is
+/// a permalink (i.e. a URL of the item's page)
+pub fn generate_guid(mode: GuidMode, link: &str, description: &str, pub_date: &str) -> Result<(String, bool)> {
+    Ok(match mode {
+        GuidMode::Permalink => (link.to_string(), true),
+        GuidMode::Uuid4 => (format!("urn:uuid:{}", Uuid::new_v4()), false),
+        GuidMode::Uuid5 => (format!("urn:uuid:{}", Uuid::new_v5(&Uuid::NAMESPACE_URL, link.as_bytes())), false),
+        GuidMode::Hash => {
+            let hash: String = Sha256::digest(description.as_bytes())
+                .iter()
+                .map(|b| format!("{:02x}", b))
+                .collect();
+            (format!("sha256:{}", hash), false)
+        }
+        GuidMode::Tag => (tag_uri(link, pub_date)?, false),
+    })
+}
+
+/// Build an RFC 4151 tag: URI, e.g. "tag:site.com,2024-01-31:/blog/page.html"
+fn tag_uri(link: &str, pub_date: &str) -> Result<String> {
+    let url = Url::parse(link)?;
+    let host = url.host_str().ok_or(Error::Url(url::ParseError::EmptyHost))?;
+    let date = DateTime::parse_from_rfc2822(pub_date)
+        .map_err(|source| Error::Date { input: pub_date.to_string(), source })?;
+    let mut specific = url.path().to_string();
+    if let Some(query) = url.query() {
+        specific.push('?');
+        specific.push_str(query);
+    }
+    Ok(format!("tag:{},{}:{}", host, date.format("%Y-%m-%d"), specific))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LINK: &str = "https://site.com/blog/page.html";
+    const DATE: &str = "Wed, 31 Jan 2024 22:30:00 +0000";
+
+    #[test]
+    fn permalink_is_the_link() {
+        assert_eq!(generate_guid(GuidMode::Permalink, LINK, "", DATE).unwrap(), (LINK.to_string(), true));
+    }
+
+    #[test]
+    fn uuid5_is_stable_and_uuid4_is_not() {
+        let a = generate_guid(GuidMode::Uuid5, LINK, "a", DATE).unwrap();
+        let b = generate_guid(GuidMode::Uuid5, LINK, "b", DATE).unwrap();
+        assert_eq!(a, b);
+        assert!(a.0.starts_with("urn:uuid:") && !a.1);
+        assert_ne!(generate_guid(GuidMode::Uuid4, LINK, "", DATE).unwrap(), generate_guid(GuidMode::Uuid4, LINK, "", DATE).unwrap());
+    }
+
+    #[test]
+    fn hash_follows_content() {
+        let (guid, _) = generate_guid(GuidMode::Hash, LINK, "abc", DATE).unwrap();
+        assert_eq!(guid, "sha256:ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+    }
+
+    #[test]
+    fn tag_uri_from_link_and_date() {
+        let (guid, _) = generate_guid(GuidMode::Tag, "https://site.com/blog/page.html?id=3", "", DATE).unwrap();
+        assert_eq!(guid, "tag:site.com,2024-01-31:/blog/page.html?id=3");
+    }
+
+    #[test]
+    fn modes_from_str() {
+        for mode in GuidMode::variants() {
+            assert_eq!(mode.to_string().parse::<GuidMode>(), Ok(mode));
+        }
+        assert_eq!("uuid5".parse::<GuidMode>(), Ok(GuidMode::Uuid5));
+        assert!("other".parse::<GuidMode>().is_err());
+    }
+}
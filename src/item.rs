@@ -0,0 +1,220 @@
+//! Construction of RSS <item> elements
+
+use crate::enclosure::Enclosure;
+use crate::error::Result;
+use crate::links::LinkRules;
+use crate::media::{MediaImage, MEDIA_NAMESPACE};
+use crate::podcast::{ItunesItem, ITUNES_NAMESPACE};
+use crate::utils;
+
+/// The Dublin Core namespace, needed for <dc:creator>
+pub const DC_NAMESPACE: (&str, &str) = ("dc", "http://purl.org/dc/elements/1.1/");
+
+/// A single RSS <item>
+#[derive(Debug, Clone)]
+pub struct RssItem {
+    /// Item title (unescaped, it is escaped when written as XML)
+    pub title: String,
+    /// URL of the item's web page
+    pub link: String,
+    /// HTML content, written inside a CDATA section
+    pub description: String,
+    /// Publication date in RFC 2822 format
+    pub pub_date: String,
+    /// Unique identifier for the item
+    pub guid: String,
+    /// Whether the guid is a URL of the item's page
+    pub guid_is_permalink: bool,
+    /// Topics the item belongs to
+    pub categories: Vec<Category>,
+    /// Author's name, or email address optionally followed by "(Name)"
+    pub author: Option<String>,
+    /// Attached media file
+    pub enclosure: Option<Enclosure>,
+    /// Podcast episode fields
+    pub itunes: ItunesItem,
+    /// Lead image for feed reader cards
+    pub image: Option<MediaImage>,
+}
+
+/// An item <category>, optionally qualified by a taxonomy `domain`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Category {
+    pub term: String,
+    pub domain: Option<String>,
+}
+
+impl Category {
+    pub fn new(term: &str, domain: Option<&str>) -> Self {
+        Category {
+            term: term.to_string(),
+            domain: domain.map(str::to_string),
+        }
+    }
+
+    /// The escaped <category> element
+    pub fn to_xml(&self) -> String {
+        match &self.domain {
+            Some(domain) => format!(r#"<category domain="{}">{}</category>"#, utils::escape_xml(domain), utils::escape_xml(&self.term)),
+            None => format!("<category>{}</category>", utils::escape_xml(&self.term)),
+        }
+    }
+}
+
+impl RssItem {
+    /// Create an item whose <guid> is its <link>, as per the RSS spec
+    pub fn new(title: &str, description: &str, link: &str, pub_date: &str) -> Self {
+        RssItem {
+            title: title.to_string(),
+            link: link.to_string(),
+            description: description.to_string(),
+            pub_date: pub_date.to_string(),
+            guid: link.to_string(),
+            guid_is_permalink: true,
+            categories: Vec::new(),
+            author: None,
+            enclosure: None,
+            itunes: ItunesItem::default(),
+            image: None,
+        }
+    }
+
+    /// Whether the author is given as an email address, as required by
+    /// the RSS <author> element, rather than just a name
+    /// (NB: "jo@site.com (Jo)" is, "@jo" and "Jo @ Site" aren't)
+    fn author_is_email(&self) -> bool {
+        let Some(address) = self.author.as_deref().and_then(|a| a.split_whitespace().next()) else { return false };
+        address.split_once('@').is_some_and(|(local, domain)| !local.is_empty() && !domain.is_empty())
+    }
+
+    /// The (prefix, URI) namespaces which must be declared on the
+    /// feed's <rss> element for this item's XML to be valid
+    pub fn namespaces(&self) -> Vec<(&'static str, &'static str)> {
+        let mut namespaces = Vec::new();
+        if self.author.is_some() && !self.author_is_email() {
+            namespaces.push(DC_NAMESPACE);
+        }
+        if !self.itunes.is_empty() {
+            namespaces.push(ITUNES_NAMESPACE);
+        }
+        if self.image.is_some() {
+            namespaces.push(MEDIA_NAMESPACE);
+        }
+        namespaces
+    }
+
+    /// The <guid> element, flagged when it isn't the item's URL
+    fn guid_xml(&self) -> String {
+        match self.guid_is_permalink {
+            true => format!("<guid>{}</guid>", utils::escape_xml(&self.guid)),
+            false => format!(r#"<guid isPermaLink="false">{}</guid>"#, utils::escape_xml(&self.guid)),
+        }
+    }
+
+    /// Generate the RSS item XML, indented to suit insertion before </channel>
+    pub fn to_xml(&self) -> String {
+        // Optional elements, each on its own line
+        let mut elements: Vec<String> = Vec::new();
+        if let Some(author) = &self.author {
+            // RSS <author> must be an email address so use <dc:creator> for names
+            elements.push(match self.author_is_email() {
+                true => format!("<author>{}</author>", utils::escape_xml(author)),
+                false => format!("<dc:creator>{}</dc:creator>", utils::escape_xml(author)),
+            });
+        }
+        elements.extend(self.categories.iter().map(Category::to_xml));
+        elements.extend(self.enclosure.iter().map(Enclosure::to_xml));
+        elements.extend(self.itunes.to_xml_elements());
+        elements.extend(self.image.iter().flat_map(MediaImage::to_xml_elements));
+        let extra: String = elements.iter()
+            .map(|e| format!("            {}\n", e))
+            .collect();
+
+        format!(r#"    <item>
+           <title>{}</title>
+            <link>{}</link>
+            <description><![CDATA[{}]]>
+            </description>
+            <pubDate>{}</pubDate>
+            {}
+{}        </item>
+    "#,
+            utils::escape_xml(&self.title),
+            utils::escape_xml(&self.link),
+            self.description,
+            self.pub_date,
+            self.guid_xml(),
+            extra
+        )
+    }
+}
+
+/// Construct the URL of the item's web page from the HTML path
+/// (see [`LinkRules::link_for`] to use path mapping rules)
+pub fn item_link(base_url: &str, html_path: &str) -> Result<String> {
+    LinkRules::default().link_for(base_url, html_path)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories_are_escaped() {
+        let mut item = RssItem::new("T", "", "https://site/", "date");
+        item.categories.push(Category::new("Q&A", None));
+        item.categories.push(Category::new("Rust", Some("https://site/tags?a=1&b=2")));
+        let xml = item.to_xml();
+        assert!(xml.contains("            <category>Q&amp;A</category>\n"));
+        assert!(xml.contains(r#"<category domain="https://site/tags?a=1&amp;b=2">Rust</category>"#));
+    }
+
+    #[test]
+    fn author_name_uses_dc_creator() {
+        let mut item = RssItem::new("T", "", "https://site/", "date");
+        item.author = Some("Jo & Co".to_string());
+        assert!(item.to_xml().contains("<dc:creator>Jo &amp; Co</dc:creator>"));
+        assert_eq!(item.namespaces(), vec![DC_NAMESPACE]);
+    }
+
+    #[test]
+    fn author_email_uses_author() {
+        let mut item = RssItem::new("T", "", "https://site/", "date");
+        item.author = Some("jo@site.com (Jo)".to_string());
+        assert!(item.to_xml().contains("<author>jo@site.com (Jo)</author>"));
+        assert!(item.namespaces().is_empty());
+    }
+
+    #[test]
+    fn author_handle_is_not_an_email() {
+        for handle in ["@jo", "jo@", "Jo @ Site"] {
+            let mut item = RssItem::new("T", "", "https://site/", "date");
+            item.author = Some(handle.to_string());
+            assert!(item.to_xml().contains(&format!("<dc:creator>{}</dc:creator>", handle)));
+            assert_eq!(item.namespaces(), vec![DC_NAMESPACE]);
+        }
+    }
+
+    #[test]
+    fn guid_not_a_permalink() {
+        let mut item = RssItem::new("T", "", "https://site/", "date");
+        assert!(item.to_xml().contains("<guid>https://site/</guid>"));
+        item.guid = "urn:uuid:1234".to_string();
+        item.guid_is_permalink = false;
+        assert!(item.to_xml().contains(r#"<guid isPermaLink="false">urn:uuid:1234</guid>"#));
+    }
+
+    #[test]
+    fn link_with_query_is_escaped() {
+        let xml = RssItem::new("T", "", "https://site.com/blog/post?a=1&b=2", "date").to_xml();
+        assert!(xml.contains("<link>https://site.com/blog/post?a=1&amp;b=2</link>"));
+        assert!(xml.contains("<guid>https://site.com/blog/post?a=1&amp;b=2</guid>"));
+    }
+
+    #[test]
+    fn no_categories() {
+        let xml = RssItem::new("T", "", "https://site/", "date").to_xml();
+        assert!(!xml.contains("<category"));
+        assert!(xml.contains("</guid>\n        </item>"));
+    }
+}
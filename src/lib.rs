@@ -0,0 +1,44 @@
+//! HTMLtoRSS - Convert HTML content to RSS feed items
+//!
+//! The library behind the `HTMLtoRSS` command-line tool, for use by static
+//! site generators and other programs which want to add items to an RSS
+//! feed without shelling out to the binary:
+//!
+//! ```no_run
+//! use htmltorss::{fetch, content, item::RssItem, feed::Feed, utils};
+//!
+//! # fn main() -> htmltorss::Result<()> {
+//! let html = fetch::read_html("blog/holiday.html")?;
+//! let content = content::process_html_content(&html, "https://site/blog", &content::ContentOptions::default())?;
+//! let link = htmltorss::item::item_link("https://site/blog", "blog/holiday.html")?;
+//! let item = RssItem::new(&content.title, &content.description, &link, &utils::now_rfc2822());
+//!
+//! let mut feed = Feed::load("blog/rss.xml")?;
+//! feed.insert_item(&item)?;
+//! feed.save()?;
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod auth;
+pub mod batch;
+pub mod cache;
+pub mod channel;
+pub mod config;
+pub mod content;
+pub mod enclosure;
+pub mod encoding;
+pub mod error;
+pub mod feed;
+pub mod fetch;
+pub mod guid;
+pub mod item;
+pub mod links;
+pub mod media;
+pub mod metadata;
+pub mod podcast;
+pub mod readability;
+pub mod utils;
+pub mod xpath;
+
+pub use error::{Error, Result};
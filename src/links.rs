@@ -0,0 +1,192 @@
+//! Rules for turning local HTML file paths into public item links
+
+use serde::Deserialize;
+use std::str::FromStr;
+
+use crate::error::{Error, Result};
+use crate::fetch::{self, STDIN};
+use crate::utils;
+
+/// Maps a local directory to the public URL it is served from,
+/// e.g. "public" → "https://site.com/"
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct PathMapping {
+    pub local: String,
+    pub url: String,
+}
+
+impl FromStr for PathMapping {
+    type Err = String;
+
+    /// Parse a mapping given as "LOCAL=URL"
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (local, url) = s.split_once('=')
+            .ok_or_else(|| format!("expected LOCAL=URL but found '{}'", s))?;
+        Ok(PathMapping { local: local.to_string(), url: url.to_string() })
+    }
+}
+
+/// How item links are computed from local HTML file paths
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LinkRules {
+    /// Local directories and their public URLs; the longest match is used
+    pub map: Vec<PathMapping>,
+    /// Remove ".html" and ".htm" from links, for sites with clean URLs
+    pub strip_extension: bool,
+    /// Link to the directory rather than to "index.html" files
+    pub index_as_directory: bool,
+}
+
+impl LinkRules {
+    /// Add another set of rules to these, e.g. command line rules to
+    /// those from the config file
+    pub fn merge(mut self, other: LinkRules) -> Self {
+        self.map.extend(other.map);
+        self.strip_extension |= other.strip_extension;
+        self.index_as_directory |= other.index_as_directory;
+        self
+    }
+
+    /// Construct the URL of an item's web page from its HTML path.
+    ///
+    /// URLs are returned unchanged. Local paths are converted by
+    /// [`LinkRules::public_url`], then have any clean URL rules applied.
+    pub fn link_for(&self, base_url: &str, html_path: &str) -> Result<String> {
+        let mut link = self.public_url(base_url, html_path)?;
+        // it's a URL to a remote site page, so no rewriting required
+        if fetch::local_path(html_path)?.is_none() {
+            return Ok(link);
+        }
+
+        if self.index_as_directory {
+            for index in ["/index.html", "/index.htm"] {
+                if let Some(dir) = link.strip_suffix(index) {
+                    link = format!("{}/", dir);
+                }
+            }
+        }
+        if self.strip_extension && !link.ends_with('/') {
+            for extension in [".html", ".htm"] {
+                if let Some(stripped) = link.strip_suffix(extension) {
+                    link = stripped.to_string();
+                    break;
+                }
+            }
+        }
+        Ok(link)
+    }
+
+    /// The public URL of a local file (e.g. an enclosure) or URL, without
+    /// the clean URL rules.
+    ///
+    /// URLs are returned unchanged. Local paths under a mapped directory
+    /// use that directory's URL, others are merged with `base_url` as before.
+    /// Pages from standard input have no path, and the path of a `file://`
+    /// URL needn't be anything like the page's URL, so they are an error
+    /// unless the path is mapped.
+    pub fn public_url(&self, base_url: &str, source: &str) -> Result<String> {
+        if source == STDIN {
+            return Err(Error::NoLink("standard input".to_string()));
+        }
+        let Some(path) = fetch::local_path(source)? else {
+            return Ok(source.to_owned());
+        };
+        let path = normalise_path(&path.to_string_lossy());
+        match self.find_mapping(&path) {
+            Some((mapping, rest)) => Ok(format!("{}/{}", mapping.url.trim_end_matches('/'), rest)),
+            None if source.starts_with("file://") => Err(Error::NoLink(source.to_string())),
+            // Merge with the base URL avoiding any path overlap
+            None => Ok(utils::merge_remove_overlap(base_url, &path)?),
+        }
+    }
+
+    /// The mapping for the longest local directory containing `path`,
+    /// with the rest of the path below that directory
+    fn find_mapping<'a>(&self, path: &'a str) -> Option<(&PathMapping, &'a str)> {
+        self.map.iter()
+            .filter_map(|m| {
+                let local = normalise_path(&m.local);
+                let local = local.trim_end_matches('/');
+                let rest = match local {
+                    "" | "." => Some(path),
+                    _ => path.strip_prefix(local).and_then(|r| r.strip_prefix('/')),
+                };
+                rest.map(|r| (local.len(), m, r))
+            })
+            .max_by_key(|(len, _, _)| *len)
+            .map(|(_, m, r)| (m, r))
+    }
+}
+
+/// Use forward slashes and remove any leading "./"
+fn normalise_path(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    path.trim_start_matches("./").to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(map: &[&str]) -> LinkRules {
+        LinkRules {
+            map: map.iter().map(|m| m.parse().unwrap()).collect(),
+            ..LinkRules::default()
+        }
+    }
+
+    #[test]
+    fn file_urls_and_stdin_need_a_mapping() {
+        let result = rules(&[]).link_for("https://site.com/blog", "file:///tmp/rv/page.html");
+        assert!(matches!(result, Err(Error::NoLink(_))));
+        assert!(matches!(rules(&[]).link_for("https://site.com/blog", STDIN), Err(Error::NoLink(_))));
+        assert_eq!(
+            rules(&["/tmp/rv=https://site.com/blog"]).link_for("https://site.com/blog", "file:///tmp/rv/page.html").unwrap(),
+            "https://site.com/blog/page.html"
+        );
+    }
+
+    #[test]
+    fn no_rules_merges_with_base_url() {
+        let link = LinkRules::default().link_for("https://site/blog", "blog/page.html").unwrap();
+        assert_eq!(link, "https://site/blog/page.html");
+    }
+
+    #[test]
+    fn longest_mapping_wins() {
+        let rules = rules(&["public=https://site.com/", "public/blog=https://blog.site.com"]);
+        assert_eq!(rules.link_for("https://x", "./public/about.html").unwrap(), "https://site.com/about.html");
+        assert_eq!(rules.link_for("https://x", "public/blog/a/b.html").unwrap(), "https://blog.site.com/a/b.html");
+        // "publicity" is not inside "public"
+        assert_eq!(rules.link_for("https://x/", "publicity/c.html").unwrap(), "https://x/publicity/c.html");
+    }
+
+    #[test]
+    fn clean_urls() {
+        let mut rules = rules(&["public/=https://site.com"]);
+        rules.strip_extension = true;
+        rules.index_as_directory = true;
+        assert_eq!(rules.link_for("https://x", "public/blog/index.html").unwrap(), "https://site.com/blog/");
+        assert_eq!(rules.link_for("https://x", "public/blog/post.htm").unwrap(), "https://site.com/blog/post");
+    }
+
+    #[test]
+    fn local_paths_starting_with_http() {
+        let link = LinkRules::default().link_for("https://site.com/", "httpdocs/p.html").unwrap();
+        assert_eq!(link, "https://site.com/httpdocs/p.html");
+    }
+
+    #[test]
+    fn public_urls_are_not_cleaned() {
+        let mut rules = rules(&["public=https://site.com/"]);
+        rules.strip_extension = true;
+        assert_eq!(rules.public_url("https://x/blog", "public/audio/ep.mp3").unwrap(), "https://site.com/audio/ep.mp3");
+        assert_eq!(rules.public_url("https://x/blog", "public/files/page.html").unwrap(), "https://site.com/files/page.html");
+    }
+
+    #[test]
+    fn bad_mapping() {
+        assert!("public".parse::<PathMapping>().is_err());
+    }
+}
@@ -1,33 +1,127 @@
 //! HTMLtoRSS - Convert HTML content to RSS feed items
 //!
 //! A command-line tool for extracting content from HTML files and adding it to RSS feeds.
-
-use clap::Parser;
-use regex::Regex;
-use scraper::{Html, Selector};
-use std::fs;
-
-mod utils;
+//! All the work is done by the `htmltorss` library; this is just the command line front end.
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::error::ErrorKind;
+use clap::{CommandFactory, Parser, Subcommand, ValueEnum};
+use serde::Serialize;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use htmltorss::channel::ChannelInfo;
+use htmltorss::config::Config;
+use htmltorss::batch;
+use htmltorss::content::{first_image, first_media_source, process_html_content, ContentOptions, ProcessedContent};
+use htmltorss::enclosure::Enclosure;
+use htmltorss::feed::Feed;
+use htmltorss::fetch::{self, FetchOptions};
+use htmltorss::guid::{generate_guid, GuidMode};
+use htmltorss::item::{Category, RssItem};
+use htmltorss::links::{LinkRules, PathMapping};
+use htmltorss::media::MediaImage;
+use htmltorss::metadata::extract_metadata;
+use htmltorss::podcast::{ItunesChannel, ItunesItem, ITUNES_NAMESPACE};
+use htmltorss::utils;
+use htmltorss::{Error, Result};
 
 /// Command line arguments for HTMLtoRSS
+///
+/// (NB: `html`, `rss` and `parent_url` are options only so that they
+/// can be left out when a subcommand is used, they are still required
+/// when adding items)
 #[derive(Parser, Debug)]
 #[clap(name = "HTMLtoRSS", version = "0.1.0", author = "bobosola@gmail.com")]
+#[clap(subcommand_negates_reqs = true, args_conflicts_with_subcommands = true)]
 struct Args {
-    /// Path to the HTML file or URL to read
-    #[clap(long = "html", short = 'f', help = "Relative path to HTML file or URL of a website page")]
-    html: String,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Paths to the HTML files or URLs to read, one item being made from each
+    #[clap(long = "html", short = 'f', required = true, help = "Relative path to HTML file, file:// or website page URL, or '-' for stdin (repeatable)")]
+    html: Vec<String>,
 
     /// Path to the RSS file to update
-    #[clap(long = "rss", short = 'r', help = "Relative path to your rss.xml file")]
-    rss: String,
+    #[clap(long = "rss", short = 'r', required = true, help = "Relative path to your rss.xml file")]
+    rss: Option<String>,
 
     /// Base URL for converting relative URLs to absolute
-    #[clap(long = "parent-url", short = 'b', help = "Parent URL to convert relative src etc. values")]
-    parent_url: String,
+    #[clap(long = "parent-url", short = 'b', required = true, help = "Parent URL to convert relative src etc. values")]
+    parent_url: Option<String>,
+
+    /// CSS selectors to extract HTML content, tried in turn until one matches
+    #[clap(long = "selector", short = 's', default_value = "main", help = "Optional CSS selector for content, or 'auto' to find it, repeat for fallbacks e.g. -s article -s main -s auto")]
+    selectors: Vec<String>,
+
+    /// XPath expression to extract HTML content, instead of the selectors
+    #[clap(long = "xpath", short = 'x', conflicts_with = "selectors", help = "Optional XPath expression for content e.g. \"//h2[contains(., 'Update')]/following-sibling::div[1]\"")]
+    xpath: Option<String>,
+
+    /// Use every element the selector matches, not just the first
+    #[clap(long = "all-matches", help = "Join the content of every element the selector matches, not just the first")]
+    all_matches: bool,
+
+    /// URL of the item's web page (defaults to the page's canonical URL, else
+    /// one made from the parent URL and the HTML path)
+    #[clap(long = "link", short = 'l', help = "Optional URL of the item's web page")]
+    link: Option<String>,
+
+    /// Don't use the page's canonical or og:url URL as the link
+    #[clap(long = "no-page-link", help = "Ignore canonical and og:url URLs found in the page")]
+    no_page_link: bool,
+
+    /// Local directories and the public URLs they are served from
+    #[clap(long = "map", help = "Optional local directory to URL mapping for links e.g. 'public=https://site.com/' (repeatable)")]
+    map: Vec<PathMapping>,
+
+    /// Remove .html and .htm from links made from local paths
+    #[clap(long = "strip-extension", help = "Remove .html/.htm from links made from local paths")]
+    strip_extension: bool,
+
+    /// Link to the directory rather than to index.html files
+    #[clap(long = "index-as-directory", help = "Link to the directory rather than to index.html files")]
+    index_as_directory: bool,
+
+    /// Config file with link rules etc. (defaults to htmltorss.toml if it exists)
+    #[clap(long = "config", help = "Optional config file [default: htmltorss.toml if present]")]
+    config: Option<String>,
+
+    /// Time allowed for each HTTP request
+    #[clap(long = "timeout", help = "Optional HTTP request timeout in seconds [default: 30]")]
+    timeout: Option<u64>,
+
+    /// Retries after HTTP connection failures, timeouts, 429 and 5xx responses
+    #[clap(long = "retries", help = "Optional number of HTTP retries [default: 2]")]
+    retries: Option<u32>,
+
+    /// User-Agent for HTTP requests
+    #[clap(long = "user-agent", help = "Optional HTTP User-Agent")]
+    user_agent: Option<String>,
+
+    /// Extra headers for HTTP requests
+    #[clap(long = "header", short = 'H', help = "Optional HTTP header e.g. 'Accept-Language: en' (repeatable)")]
+    headers: Vec<String>,
+
+    /// Character encoding of the HTML, overriding detection
+    #[clap(long = "encoding", help = "Optional HTML character encoding e.g. 'windows-1252' [default: detected]")]
+    encoding: Option<String>,
+
+    /// Directory to cache fetched pages in
+    #[clap(long = "cache", help = "Optional directory to cache fetched pages in, only downloading them again if changed")]
+    cache: Option<PathBuf>,
 
-    /// CSS selector to extract HTML content
-    #[clap(long = "selector", short = 's', default_value = "main", help = "Optional CSS selector for content")]
-    selector: String,
+    /// Only use cached pages
+    #[clap(long = "offline", help = "Only use pages from the --cache directory, never the network")]
+    offline: bool,
+
+    /// Most pages to read at once
+    #[clap(long = "concurrency", help = "Optional number of pages to read at once with several --html pages [default: 4]")]
+    concurrency: Option<usize>,
+
+    /// Least time between requests to the same host, in milliseconds
+    #[clap(long = "host-delay", help = "Optional delay in milliseconds between requests to the same host [default: 250]")]
+    host_delay: Option<u64>,
 
     /// Title for the RSS item (defaults to first <h1> text)
     #[clap(long = "title", short = 't', help = "Optional title else first <h1> text is used")]
@@ -41,35 +135,279 @@ struct Args {
     #[clap(long = "lines-to-cut", short = 'c', default_value = "0", help = "Optional lines to cut")]
     lines_to_cut: usize,
 
+    /// Author of the item (defaults to the author found in the page)
+    #[clap(long = "author", short = 'a', help = "Optional author name, or email address e.g. 'me@site.com (My Name)'")]
+    author: Option<String>,
+
+    /// Categories for the item, in addition to any tags found in the page
+    #[clap(long = "category", help = "Optional category for the item (repeatable)")]
+    categories: Vec<String>,
+
+    /// Taxonomy domain for the categories given on the command line
+    #[clap(long = "category-domain", help = "Optional domain attribute for --category values")]
+    category_domain: Option<String>,
+
+    /// Don't add categories from the page's keywords and tags
+    #[clap(long = "no-page-categories", help = "Ignore keywords and tags found in the page")]
+    no_page_categories: bool,
+
+    /// Media file to attach to the item (defaults to the first <audio> or <video> in the content)
+    #[clap(long = "enclosure", short = 'e', help = "Optional relative path or URL of an audio, video or other file")]
+    enclosure: Option<String>,
+
+    /// Don't attach the first <audio> or <video> in the content
+    #[clap(long = "no-page-enclosure", help = "Ignore audio and video found in the content")]
+    no_page_enclosure: bool,
+
+    /// Lead image for the item (defaults to the first image in the content, else og:image)
+    #[clap(long = "image", short = 'i', help = "Optional relative path or URL of the item's thumbnail image")]
+    image: Option<String>,
+
+    /// Don't add a thumbnail from the page's images
+    #[clap(long = "no-page-image", help = "Ignore images found in the page")]
+    no_page_image: bool,
+
+    /// Podcast episode running time (defaults to the enclosure's ID3 TLEN)
+    #[clap(long = "itunes-duration", help = "Optional podcast episode duration e.g. '1:02:03'")]
+    itunes_duration: Option<String>,
+
+    /// Podcast episode number (defaults to the enclosure's ID3 track number)
+    #[clap(long = "itunes-episode", help = "Optional podcast episode number")]
+    itunes_episode: Option<u32>,
+
+    /// Whether the podcast episode has explicit content
+    #[clap(long = "itunes-explicit", help = "Optional podcast episode explicit flag [possible values: true, false]")]
+    itunes_explicit: Option<bool>,
+
+    /// Podcast episode artwork
+    #[clap(long = "itunes-image", help = "Optional relative path or URL of the podcast episode image")]
+    itunes_image: Option<String>,
+
+    /// Podcast episode summary (defaults to the enclosure's first ID3 comment)
+    #[clap(long = "itunes-summary", help = "Optional podcast episode summary")]
+    itunes_summary: Option<String>,
+
+    /// Whether the podcast as a whole has explicit content
+    #[clap(long = "itunes-channel-explicit", help = "Optional podcast channel explicit flag [possible values: true, false]")]
+    itunes_channel_explicit: Option<bool>,
+
+    /// Podcast channel artwork
+    #[clap(long = "itunes-channel-image", help = "Optional relative path or URL of the podcast channel image")]
+    itunes_channel_image: Option<String>,
+
+    /// Podcast channel summary
+    #[clap(long = "itunes-channel-summary", help = "Optional podcast channel summary")]
+    itunes_channel_summary: Option<String>,
+
+    /// Set the channel's <pubDate> to the newest item's date
+    #[clap(long = "update-pub-date", help = "Set the channel pubDate to the newest item date")]
+    update_pub_date: bool,
+
+    /// How to generate the item's <guid>
+    #[clap(long = "guid-mode", value_parser = guid_mode_parser(), default_value = "permalink", help = "Optional guid type")]
+    guid_mode: GuidMode,
+
+    /// A fixed <guid> for the item, overriding --guid-mode
+    #[clap(long = "guid", short = 'g', help = "Optional fixed guid for the item")]
+    guid: Option<String>,
+
     /// Dry run mode - only display output to terminal
     #[clap(long = "dry-run")]
     dry_run: bool,
+
+    /// Dry run mode showing a unified diff of the changes to the RSS file
+    #[clap(long = "diff", help = "Dry run mode - show a unified diff of the rss.xml changes")]
+    diff: bool,
+
+    /// Format of the results written to the terminal
+    #[clap(long = "output", short = 'o', value_enum, default_value = "text", help = "Optional output format")]
+    output: OutputFormat,
+}
+
+/// Subcommands other than the default of adding an item
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Show or update the channel's title, description etc.
+    Channel(ChannelArgs),
 }
 
-fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// Command line arguments for the `channel` subcommand
+#[derive(clap::Args, Debug)]
+struct ChannelArgs {
+    /// Path to the RSS file to read or update
+    #[clap(long = "rss", short = 'r', help = "Relative path to your rss.xml file")]
+    rss: String,
+
+    #[clap(long = "title", help = "Optional new channel title")]
+    title: Option<String>,
+
+    #[clap(long = "link", help = "Optional new channel link URL")]
+    link: Option<String>,
+
+    #[clap(long = "description", help = "Optional new channel description")]
+    description: Option<String>,
+
+    #[clap(long = "language", help = "Optional new channel language e.g. 'en-gb'")]
+    language: Option<String>,
+
+    #[clap(long = "image", help = "Optional new channel image URL")]
+    image: Option<String>,
+
+    #[clap(long = "ttl", help = "Optional new channel time to live in minutes")]
+    ttl: Option<u32>,
+
+    #[clap(long = "copyright", help = "Optional new channel copyright notice")]
+    copyright: Option<String>,
+
+    #[clap(long = "managing-editor", help = "Optional new channel editor e.g. 'me@site.com (My Name)'")]
+    managing_editor: Option<String>,
+
+    /// Set the channel's <pubDate> to the newest item's date
+    #[clap(long = "update-pub-date", help = "Set the channel pubDate to the newest item date")]
+    update_pub_date: bool,
+
+    /// Dry run mode showing a unified diff of the changes to the RSS file
+    #[clap(long = "diff", help = "Dry run mode - show a unified diff of the rss.xml changes")]
+    diff: bool,
+
+    /// Format of the results written to the terminal
+    #[clap(long = "output", short = 'o', value_enum, default_value = "text", help = "Optional output format")]
+    output: OutputFormat,
+}
 
+/// Parse `--guid-mode`, listing the modes in the help
+fn guid_mode_parser() -> impl TypedValueParser<Value = GuidMode> {
+    PossibleValuesParser::new(GuidMode::variants().map(GuidMode::name))
+        .map(|name| name.parse().expect("only listed guid modes are accepted"))
+}
+
+/// Terminal output formats
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+enum OutputFormat {
+    /// Human-readable text
+    Text,
+    /// A single JSON object for use by scripts
+    Json,
+}
+
+/// Results for one item, as emitted by `--output json`
+/// (as an array of them when there are several pages)
+#[derive(Serialize)]
+struct RunReport<'a> {
+    title: &'a str,
+    link: &'a str,
+    guid: &'a str,
+    #[serde(rename = "pubDate")]
+    pub_date: &'a str,
+    description: &'a str,
+    selector: &'a str,
+    word_count: usize,
+    rewritten_urls: &'a [String],
+    categories: Vec<&'a str>,
+    author: Option<&'a str>,
+    enclosure: Option<&'a str>,
+    image: Option<&'a str>,
+    rss_file: &'a str,
+    dry_run: bool,
+}
+
+/// Channel fields, as emitted by `channel --output json`
+#[derive(Serialize)]
+struct ChannelReport<'a> {
+    title: Option<&'a str>,
+    link: Option<&'a str>,
+    description: Option<&'a str>,
+    language: Option<&'a str>,
+    image: Option<&'a str>,
+    ttl: Option<u32>,
+    copyright: Option<&'a str>,
+    #[serde(rename = "managingEditor")]
+    managing_editor: Option<&'a str>,
+}
+
+impl<'a> From<&'a ChannelInfo> for ChannelReport<'a> {
+    fn from(info: &'a ChannelInfo) -> Self {
+        ChannelReport {
+            title: info.title.as_deref(),
+            link: info.link.as_deref(),
+            description: info.description.as_deref(),
+            language: info.language.as_deref(),
+            image: info.image.as_deref(),
+            ttl: info.ttl,
+            copyright: info.copyright.as_deref(),
+            managing_editor: info.managing_editor.as_deref(),
+        }
+    }
+}
+
+fn main() -> ExitCode {
     let args = Args::parse();
 
-    // Get the content of the HTML file, either from a URL or a local file path
-    let html_content = if args.html.starts_with("http://") || args.html.starts_with("https://") {
-        // It's a URL so fetch it
-        let client = reqwest::blocking::Client::new();
-        client.get(&args.html).send()?.text()?
-    } else {
-        // Read the local file
-        fs::read_to_string(&args.html)?
+    // Options describing a single page make no sense with several
+    if args.html.len() > 1 {
+        let single_page_options = [
+            ("--title", args.title.is_some()),
+            ("--link", args.link.is_some()),
+            ("--guid", args.guid.is_some()),
+            ("--enclosure", args.enclosure.is_some()),
+            ("--image", args.image.is_some()),
+            ("--itunes-episode", args.itunes_episode.is_some()),
+            ("--itunes-duration", args.itunes_duration.is_some()),
+        ];
+        if let Some((option, _)) = single_page_options.iter().find(|(_, given)| *given) {
+            Args::command()
+                .error(ErrorKind::ArgumentConflict, format!("{} can't be used with more than one --html page", option))
+                .exit();
+        }
+
+        // Standard input can only be read once, and reading a page twice
+        // would only add the same item twice
+        if let Err(e) = batch::check_sources(&args.html) {
+            Args::command().error(ErrorKind::ArgumentConflict, e).exit();
+        }
+    }
+
+    // Report any failure on stderr with an exit code specific to its kind
+    let result = match &args.command {
+        Some(Command::Channel(channel_args)) => run_channel(channel_args),
+        None => run(&args),
     };
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(e.exit_code())
+        }
+    }
+}
 
-    // Process the file's HTML content to extract the
-    // RSS item's <title> and <description> elements
-    // (NB: the <description> element holds the HTML page content)
-    let (item_title, item_description) = process_html_content(
-        &html_content,
-        &args.parent_url,
-        &args.selector,
-        args.title.as_ref(),
-        args.lines_to_cut,
-    )?;
+/// Create an RSS item for each page and insert them into the RSS file
+fn run(args: &Args) -> Result<()> {
+
+    // (NB: clap ensures these are given when there's no subcommand)
+    let rss = args.rss.as_deref().unwrap_or_default();
+    let parent_url = args.parent_url.as_deref().unwrap_or_default();
+
+    // Check the parent URL up front as all URL rewriting depends on it
+    url::Url::parse(parent_url)?;
+
+    // Settings from the config file, with command line settings added
+    let config = Config::load_or_default(args.config.as_deref())?;
+    let link_rules = config.links.merge(LinkRules {
+        map: args.map.clone(),
+        strip_extension: args.strip_extension,
+        index_as_directory: args.index_as_directory,
+    });
+
+    // HTTP settings from the config file, overridden by the command line
+    let fetch_options = fetch_options(args, config.fetch)?;
+
+    // Get the content of the HTML pages, either from URLs, local file paths or stdin,
+    // reading several at once if there are more than one
+    let pages = match args.html.as_slice() {
+        [html] => vec![fetch::read_html_with(html, &fetch_options)],
+        htmls => batch::read_all_html(htmls, &fetch_options)?,
+    };
 
     // Get the user-supplied date or else use now
     // and convert to RFC 2822 to match RSS spec
@@ -78,183 +416,339 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         utils::now_rfc2822()
     }
     else {
-        match utils::parse_to_rfc2822(&args.date_time){
-            Ok(d_rfc) => d_rfc,
-            Err(_) => "INVALID DATE ENTERED".to_string()
-        }
+        utils::parse_to_rfc2822(&args.date_time)
+            .map_err(|source| Error::Date { input: args.date_time.clone(), source })?
+    };
+
+    // Make the items in the same order as the pages were given
+    let mut rss_items = Vec::new();
+    let mut contents = Vec::new();
+    for (html, html_content) in args.html.iter().zip(pages) {
+        let (rss_item, content) = make_item(args, html, &html_content?, parent_url, &pub_date, &link_rules, &fetch_options)?;
+        rss_items.push(rss_item);
+        contents.push(content);
+    }
+
+    // Podcast fields for the channel as a whole
+    let itunes_channel = ItunesChannel {
+        explicit: args.itunes_channel_explicit,
+        image: args.itunes_channel_image.as_deref()
+            .map(|image| utils::merge_url_and_fragment(parent_url, image))
+            .transpose()?,
+        summary: args.itunes_channel_summary.clone(),
     };
 
-        // Generate the new RSS item
-    let rss_item = generate_rss_item(
-        &item_title,
-        &item_description,
-        &args.parent_url,
-        &args.html,
-        &pub_date
-    )?;
+    let reports: Vec<RunReport> = rss_items.iter().zip(&contents)
+        .map(|(rss_item, content)| RunReport {
+            title: &content.title,
+            link: &rss_item.link,
+            guid: &rss_item.guid,
+            pub_date: &rss_item.pub_date,
+            description: &content.description,
+            selector: &content.selector,
+            word_count: content.word_count,
+            rewritten_urls: &content.rewritten_urls,
+            categories: rss_item.categories.iter().map(|c| c.term.as_str()).collect(),
+            author: rss_item.author.as_deref(),
+            enclosure: rss_item.enclosure.as_ref().map(|e| e.url.as_str()),
+            image: rss_item.image.as_ref().map(|i| i.url.as_str()),
+            rss_file: rss,
+            dry_run: args.dry_run,
+        })
+        .collect();
+
+    // If in diff mode, show what the rss.xml file would look like and exit
+    if args.diff {
+        let mut feed = Feed::load(rss)?;
+        update_feed(&mut feed, &rss_items, &itunes_channel, args.update_pub_date)?;
+        feed.set_last_build_date(&utils::now_rfc2822())?;
+        print!("{}", feed.diff());
+        return Ok(());
+    }
 
-    // If in dry run mode, print item to terminal and exit
+    // If in dry run mode, print the items to terminal and exit
     if args.dry_run {
-        println!("=== DRY RUN MODE ===");
-        println!("Title: {}", item_title);
-        println!("Base URL: {}", args.parent_url);
-        println!("Selector used: {}", args.selector);
-        if args.lines_to_cut > 0 {
-            println!("Lines to cut: {}", args.lines_to_cut);
+        if args.output == OutputFormat::Json {
+            println!("{}", json_report(&reports)?);
+            return Ok(());
         }
-        if let Some(t) = args.title {
-            println!("Title override: {}", t);
+        println!("=== DRY RUN MODE ===");
+        for (rss_item, content) in rss_items.iter().zip(&contents) {
+            println!("Title: {}", content.title);
+            println!("Base URL: {}", parent_url);
+            println!("Selector used: {}", content.selector);
+            if args.lines_to_cut > 0 {
+                println!("Lines to cut: {}", args.lines_to_cut);
+            }
+            if let Some(t) = &args.title {
+                println!("Title override: {}", t);
+            }
+            if let Some(author) = &rss_item.author {
+                println!("Author: {}", author);
+            }
+            if let Some(enclosure) = &rss_item.enclosure {
+                println!("Enclosure: {} ({} bytes, {})", enclosure.url, enclosure.length, enclosure.mime_type);
+            }
+            if let Some(image) = &rss_item.image {
+                println!("Image: {}", image.url);
+            }
+            if !rss_item.categories.is_empty() {
+                let terms: Vec<&str> = rss_item.categories.iter().map(|c| c.term.as_str()).collect();
+                println!("Categories: {}", terms.join(", "));
+            }
+            println!("RSS Item:");
+            println!("{}", rss_item.to_xml());
         }
-        println!("RSS Item:");
-        println!("{}", rss_item);
         return Ok(());
     }
 
-    // Insert the new item at the end of the </channel> element in the rss.xml file
-    let place_before = "</channel>";
-    match utils::insert_before_text(&args.rss, &place_before, &rss_item) {
-        Ok(_) => {
-            println!("RSS item successfully added to {}", args.rss)
-        },
-        Err(e) => println!("Error writing to rss.xml file: {}", e)
+    // Insert the new items at the end of the </channel> element in the rss.xml file
+    let mut feed = Feed::load(rss)?;
+    update_feed(&mut feed, &rss_items, &itunes_channel, args.update_pub_date)?;
+    feed.save()?;
+    match args.output {
+        OutputFormat::Json => println!("{}", json_report(&reports)?),
+        OutputFormat::Text if rss_items.len() == 1 => println!("RSS item successfully added to {}", rss),
+        OutputFormat::Text => println!("{} RSS items successfully added to {}", rss_items.len(), rss),
     };
     Ok(())
 }
 
-/// Process HTML content and convert it to RSS item format
-fn process_html_content(
+/// Create the RSS item for one page
+fn make_item(
+    args: &Args,
+    html: &str,
     html_content: &str,
-    base_url: &str,
-    selector: &str,
-    title: Option<&String>,
-    lines_to_cut: usize,
-) -> Result<(String, String), Box<dyn std::error::Error>> {
-
-    let document = Html::parse_document(html_content);
-
-    // Find the selector
-    let selector_obj = Selector::parse(selector).map_err(|_| "Invalid CSS selector")?;
-    let element = document
-        .select(&selector_obj)
-        .next()
-        .ok_or("Selector not found in HTML")?;
-
-    // Get the inner HTML content
-    let mut html_content = element.inner_html();
-
-    // Cut lines if specified
-    if lines_to_cut > 0 {
-        let mut lines: Vec<&str> = html_content.lines().collect();
-        if lines_to_cut < lines.len() {
-            lines.drain(..lines_to_cut);
-            html_content = lines.join("\n");
+    parent_url: &str,
+    pub_date: &str,
+    link_rules: &LinkRules,
+    fetch_options: &FetchOptions,
+) -> Result<(RssItem, ProcessedContent)> {
+
+    // Local enclosures are looked for next to the file that any
+    // file:// URL refers to
+    let html_path = match fetch::local_path(html)? {
+        Some(path) => path.to_string_lossy().into_owned(),
+        None => html.to_string(),
+    };
+
+    // Process the file's HTML content to extract the
+    // RSS item's <title> and <description> elements
+    // (NB: the <description> element holds the HTML page content)
+    let content = process_html_content(html_content, parent_url, &content_options(args))?;
+
+    // Look for tags, author etc. in the whole page
+    let metadata = extract_metadata(html_content);
+
+    // Construct the <link> element as a URL to the item's web page, using the
+    // command line link, else the page's canonical URL, else the HTML path
+    // (NB: by default this is also used as the <guid> element as per RSS spec)
+    let page_link = metadata.canonical_url.as_ref().filter(|_| !args.no_page_link);
+    let link = match args.link.as_ref().or(page_link) {
+        Some(link) => utils::merge_url_and_fragment(parent_url, link)?,
+        None => link_rules.link_for(parent_url, html)?,
+    };
+
+    // Generate the new RSS item
+    let mut rss_item = RssItem::new(
+        &content.title,
+        &content.description,
+        &link,
+        pub_date
+    );
+
+    // Use the pinned guid, else generate one
+    (rss_item.guid, rss_item.guid_is_permalink) = match &args.guid {
+        Some(guid) => (guid.clone(), *guid == link),
+        None => generate_guid(args.guid_mode, &link, &content.description, pub_date)?,
+    };
+
+    // Use the command line author else any found in the page
+    rss_item.author = args.author.clone().or(metadata.author);
+
+    // Add the command line categories followed by any found in the page
+    for term in &args.categories {
+        rss_item.categories.push(Category::new(term, args.category_domain.as_deref()));
+    }
+    if !args.no_page_categories {
+        for term in &metadata.categories {
+            if !rss_item.categories.iter().any(|c| c.term.eq_ignore_ascii_case(term)) {
+                rss_item.categories.push(Category::new(term, None));
+            }
         }
     }
 
-    // Clean up whitespace
-    let re_whitespace = Regex::new(r"\s+")?;
-    html_content = re_whitespace.replace_all(&html_content, " ").to_string();
-
-    // Extract title from first h1 if not provided as an arg
-    let item_title = match title {
-        Some(t) => t.clone(),
-        None => {
-            // Find first h1 element
-            let h1_selector = Selector::parse("h1").map_err(|_| "Invalid H1 selector")?;
-            if let Some(h1_element) = document.select(&h1_selector).next() {
-                h1_element.text().collect::<Vec<_>>().join(" ")
-            } else {
-                "Untitled".to_string()
+    // Attach the command line media file else the first found in the content
+    // (NB: only a problem with the command line file is fatal, media found
+    // in the page is just left out with a warning)
+    if let Some(source) = &args.enclosure {
+        rss_item.enclosure = Some(Enclosure::from_source(source, parent_url, &html_path, link_rules, fetch_options)?);
+    } else if !args.no_page_enclosure {
+        if let Some(source) = first_media_source(&content.description) {
+            match Enclosure::from_source(&source, parent_url, &html_path, link_rules, fetch_options) {
+                Ok(enclosure) => rss_item.enclosure = Some(enclosure),
+                Err(e) => eprintln!("Warning: leaving out the enclosure for {}: {}", source, e),
             }
         }
+    }
+
+    // Use the command line image, else the first in the content, else the Open Graph image
+    rss_item.image = match &args.image {
+        Some(image) => Some(MediaImage::new(image)),
+        None if !args.no_page_image => first_image(&content.description).or(metadata.image),
+        None => None,
     };
+    // (NB: only the og:image and --image URLs can still be relative)
+    if let Some(image) = &mut rss_item.image {
+        image.url = utils::merge_url_and_fragment(parent_url, &image.url)?;
+    }
 
-    // Convert any relative URLs to absolute
-    let mut processed_html = html_content;
-
-    // Process src, href and srcset attributes
-    let re_src = Regex::new(r#"src\s*=\s*"([^"]*)""#)?;
-    let re_href = Regex::new(r#"href\s*=\s*"([^"]*)""#)?;
-    let re_srcset = Regex::new(r#"srcset\s*=\s*"([^"]*)""#)?;
-
-    // Process src attributes
-    processed_html = re_src.replace_all(&processed_html, |caps: &regex::Captures| {
-        let attr_value = &caps[1];
-        if !attr_value.starts_with("http") {
-            let absolute_url = utils::merge_url_and_fragment(base_url, attr_value).unwrap();
-            format!("src=\"{}\"", absolute_url)
-        } else {
-            caps[0].to_string()
-        }
-    }).to_string();
-
-    // Process href attributes
-    processed_html = re_href.replace_all(&processed_html, |caps: &regex::Captures| {
-        let attr_value = &caps[1];
-        if !attr_value.starts_with("http") {
-            let absolute_url = utils::merge_url_and_fragment(base_url, attr_value).unwrap();
-            format!("href=\"{}\"", absolute_url)
-        } else {
-            caps[0].to_string()
-        }
-    }).to_string();
-
-    // Process srcset attributes
-    processed_html = re_srcset.replace_all(&processed_html, |caps: &regex::Captures| {
-        let attr_value = &caps[1];
-        // Handle multiple URLs in srcset
-        let urls: Vec<&str> = attr_value.split(',').map(|s| s.trim()).collect();
-        let processed_urls: Vec<String> = urls.iter().map(|url| {
-            if !url.starts_with("http") {
-                let absolute_url = utils::merge_url_and_fragment(base_url, attr_value).unwrap();
-                absolute_url.to_string()
-            } else {
-                url.to_string()
-            }
-        }).collect();
+    // Add podcast fields from the command line, else from the enclosure's audio file
+    let itunes_image = args.itunes_image.as_deref()
+        .map(|image| utils::merge_url_and_fragment(parent_url, image))
+        .transpose()?;
+    let itunes_flags = ItunesItem {
+        duration: args.itunes_duration.clone(),
+        episode: args.itunes_episode,
+        explicit: args.itunes_explicit,
+        image: itunes_image,
+        summary: args.itunes_summary.clone(),
+    };
+    rss_item.itunes = match rss_item.enclosure.as_ref().and_then(|e| e.path.as_ref()) {
+        Some(path) => itunes_flags.or(ItunesItem::from_audio_file(path)),
+        None => itunes_flags,
+    };
 
-        format!("srcset=\"{}\"", processed_urls.join(", "))
-    }).to_string();
+    Ok((rss_item, content))
+}
+
+/// The JSON report of a run, a single object for one item else an array
+fn json_report(reports: &[RunReport]) -> Result<String> {
+    match reports {
+        [report] => Ok(serde_json::to_string_pretty(report)?),
+        reports => Ok(serde_json::to_string_pretty(reports)?),
+    }
+}
 
-    Ok((item_title, processed_html))
+/// Which part of each page to use, from the command line
+fn content_options(args: &Args) -> ContentOptions {
+    ContentOptions {
+        selectors: args.selectors.clone(),
+        xpath: args.xpath.clone(),
+        all_matches: args.all_matches,
+        title: args.title.clone(),
+        lines_to_cut: args.lines_to_cut,
+    }
 }
 
-/// Generate RSS item XML
-fn generate_rss_item(
-    title: &str,
-    description_html: &str,
-    base_url: &str,
-    html_path: &str,
-    date_time: &str
-) -> Result<String, url::ParseError> {
-
-    // Construct the <link> element as a URL to the item's web page
-    // (NB: this is also used as the <guid> element as per RSS spec)
-    let link = match html_path.starts_with("http") {
-        // it's a URL to a remote site page, so no merging required
-        true => html_path.to_owned(),
-        // Its a local file path, so merge with the base URL
-        // avoiding any path overlap
-        false => {
-            utils::merge_remove_overlap(&base_url, html_path)?
+/// Apply the command line HTTP settings to those from the config file
+fn fetch_options(args: &Args, mut options: FetchOptions) -> Result<FetchOptions> {
+    // (NB: credentials only come from the config file or environment,
+    // and are only sent to the sites of the pages and any configured hosts)
+    options.auth = options.auth.with_env().with_hosts_of(&args.html);
+    if let Some(timeout) = args.timeout {
+        options.timeout_secs = timeout;
+    }
+    if let Some(retries) = args.retries {
+        options.retries = retries;
+    }
+    if let Some(user_agent) = &args.user_agent {
+        options.user_agent = user_agent.clone();
+    }
+    if let Some(encoding) = &args.encoding {
+        htmltorss::encoding::encoding_for_label(encoding)?;
+        options.encoding = Some(encoding.clone());
+    }
+    if let Some(cache) = &args.cache {
+        options.cache_dir = Some(cache.clone());
+    }
+    options.offline |= args.offline;
+    if let Some(concurrency) = args.concurrency {
+        options.concurrency = concurrency;
+    }
+    if let Some(host_delay) = args.host_delay {
+        options.host_delay_ms = host_delay;
+    }
+    for header in &args.headers {
+        let (name, value) = fetch::parse_header(header)?;
+        options.headers.insert(name, value);
+    }
+    if options.offline && options.cache_dir.is_none() {
+        Args::command()
+            .error(ErrorKind::MissingRequiredArgument, "--offline needs a --cache directory (or fetch.cache_dir in the config file)")
+            .exit();
+    }
+    options.check()?;
+    Ok(options)
+}
+
+/// Add the items and any channel-level podcast fields to the feed
+fn update_feed(feed: &mut Feed, rss_items: &[RssItem], itunes_channel: &ItunesChannel, update_pub_date: bool) -> Result<()> {
+    if !itunes_channel.is_empty() {
+        feed.ensure_namespace(ITUNES_NAMESPACE.0, ITUNES_NAMESPACE.1)?;
+        for (tag, xml) in itunes_channel.to_xml_elements() {
+            feed.set_channel_element(tag, &xml)?;
         }
+    }
+    for rss_item in rss_items {
+        feed.insert_item(rss_item)?;
+    }
+    if update_pub_date {
+        feed.set_pub_date_to_newest_item()?;
+    }
+    Ok(())
+}
+
+/// Show or update the channel metadata
+fn run_channel(args: &ChannelArgs) -> Result<()> {
+    let mut feed = Feed::load(&args.rss)?;
+
+    let update = ChannelInfo {
+        title: args.title.clone(),
+        link: args.link.clone(),
+        description: args.description.clone(),
+        language: args.language.clone(),
+        image: args.image.clone(),
+        ttl: args.ttl,
+        copyright: args.copyright.clone(),
+        managing_editor: args.managing_editor.clone(),
     };
+    update.update(&mut feed)?;
+    if args.update_pub_date {
+        feed.set_pub_date_to_newest_item()?;
+    }
 
-    let escaped_title = utils::escape_xml(title);
-
-    Ok(format!(r#"    <item>
-           <title>{}</title>
-            <link>{}</link>
-            <description><![CDATA[{}]]>
-            </description>
-            <pubDate>{}</pubDate>
-            <guid>{}</guid>
-        </item>
-    "#,
-        escaped_title,
-        link,
-        description_html,
-        date_time,
-        link
-    ))
+    if args.diff {
+        if feed.is_modified() {
+            feed.set_last_build_date(&utils::now_rfc2822())?;
+        }
+        print!("{}", feed.diff());
+        return Ok(());
+    }
+
+    // Save any changes before showing the resulting channel fields,
+    // so a failed save doesn't show values which weren't written
+    let info = ChannelInfo::read(&feed);
+    if feed.is_modified() {
+        feed.save()?;
+    }
+    match args.output {
+        OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&ChannelReport::from(&info))?),
+        OutputFormat::Text => {
+            let fields = [
+                ("Title", info.title),
+                ("Link", info.link),
+                ("Description", info.description),
+                ("Language", info.language),
+                ("Image", info.image),
+                ("TTL", info.ttl.map(|t| t.to_string())),
+                ("Copyright", info.copyright),
+                ("Managing editor", info.managing_editor),
+            ];
+            for (name, value) in fields {
+                println!("{}: {}", name, value.unwrap_or_default());
+            }
+        }
+    }
+    Ok(())
 }
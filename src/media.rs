@@ -0,0 +1,61 @@
+//! Media RSS thumbnails for RSS items
+
+use crate::utils;
+
+/// The Media RSS namespace, needed for all <media:*> elements
+pub const MEDIA_NAMESPACE: (&str, &str) = ("media", "http://search.yahoo.com/mrss/");
+
+/// An item's lead image, written as <media:thumbnail> and <media:content>
+#[derive(Debug, Clone, PartialEq)]
+pub struct MediaImage {
+    /// Absolute URL of the image
+    pub url: String,
+    /// Width in pixels, if known
+    pub width: Option<u32>,
+    /// Height in pixels, if known
+    pub height: Option<u32>,
+}
+
+impl MediaImage {
+    pub fn new(url: &str) -> Self {
+        MediaImage { url: url.to_string(), width: None, height: None }
+    }
+
+    /// Set the size from HTML attribute values, ignoring any which
+    /// aren't plain pixel sizes (e.g. "50%")
+    pub fn with_size(mut self, width: Option<&str>, height: Option<&str>) -> Self {
+        let pixels = |v: Option<&str>| v.and_then(|v| v.trim().trim_end_matches("px").parse().ok());
+        self.width = pixels(width);
+        self.height = pixels(height);
+        self
+    }
+
+    /// The <media:thumbnail> and <media:content> elements
+    pub fn to_xml_elements(&self) -> Vec<String> {
+        let mut attributes = format!(r#"url="{}""#, utils::escape_xml(&self.url));
+        if let Some(width) = self.width {
+            attributes.push_str(&format!(r#" width="{}""#, width));
+        }
+        if let Some(height) = self.height {
+            attributes.push_str(&format!(r#" height="{}""#, height));
+        }
+        vec![
+            format!("<media:thumbnail {} />", attributes),
+            format!(r#"<media:content {} medium="image" />"#, attributes),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn elements_with_and_without_size() {
+        let image = MediaImage::new("https://site/a.png?x=1&y=2").with_size(Some("300"), Some("50%"));
+        assert_eq!(image.to_xml_elements(), vec![
+            r#"<media:thumbnail url="https://site/a.png?x=1&amp;y=2" width="300" />"#,
+            r#"<media:content url="https://site/a.png?x=1&amp;y=2" width="300" medium="image" />"#,
+        ]);
+    }
+}
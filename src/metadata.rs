@@ -0,0 +1,212 @@
+//! Extraction of item metadata from the whole HTML page
+//!
+//! Unlike the item content, which comes from the selected element only,
+//! metadata such as tags is usually found in the page <head> or scattered
+//! around the page, so the full document is searched.
+
+use scraper::{Html, Selector};
+use serde_json::Value;
+
+use crate::media::MediaImage;
+
+/// Metadata found in an HTML page
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PageMetadata {
+    /// Topics from `<meta name="keywords">`, `article:tag` and `rel="tag"` links
+    pub categories: Vec<String>,
+    /// Author from `<meta name="author">`, a `rel="author"` link or JSON-LD
+    pub author: Option<String>,
+    /// Lead image from `<meta property="og:image">`, possibly a relative URL
+    pub image: Option<MediaImage>,
+    /// The page's own URL from `<link rel="canonical">` or `og:url`
+    pub canonical_url: Option<String>,
+}
+
+/// Extract the metadata from an HTML page
+pub fn extract_metadata(html_content: &str) -> PageMetadata {
+    let document = Html::parse_document(html_content);
+    PageMetadata {
+        categories: page_categories(&document),
+        author: page_author(&document),
+        image: page_image(&document),
+        canonical_url: page_canonical_url(&document),
+    }
+}
+
+/// Collect the page's tags in document order, ignoring duplicates
+fn page_categories(document: &Html) -> Vec<String> {
+    let mut categories: Vec<String> = Vec::new();
+    let mut add = |term: &str| {
+        let term = term.split_whitespace().collect::<Vec<_>>().join(" ");
+        if !term.is_empty() && !categories.iter().any(|c| c.eq_ignore_ascii_case(&term)) {
+            categories.push(term);
+        }
+    };
+
+    // Comma-separated keywords
+    for element in document.select(&selector(r#"meta[name="keywords" i]"#)) {
+        if let Some(keywords) = element.value().attr("content") {
+            keywords.split(',').for_each(&mut add);
+        }
+    }
+
+    // Open Graph article tags, one per element
+    for element in document.select(&selector(r#"meta[property="article:tag"]"#)) {
+        if let Some(tag) = element.value().attr("content") {
+            add(tag);
+        }
+    }
+
+    // Links to tag pages, e.g. <a href="/tags/rust" rel="tag">Rust</a>
+    for element in document.select(&selector(r#"a[rel~="tag"]"#)) {
+        add(&element.text().collect::<String>());
+    }
+
+    categories
+}
+
+/// Find the page's author, preferring the most explicit source
+fn page_author(document: &Html) -> Option<String> {
+    let clean = |s: &str| {
+        let s = s.split_whitespace().collect::<Vec<_>>().join(" ");
+        (!s.is_empty()).then_some(s)
+    };
+
+    // <meta name="author" content="...">
+    let meta = document.select(&selector(r#"meta[name="author" i]"#))
+        .find_map(|e| e.value().attr("content").and_then(clean));
+
+    // <a rel="author">Name</a>
+    let rel = || document.select(&selector(r#"a[rel~="author"]"#))
+        .find_map(|e| clean(&e.text().collect::<String>()));
+
+    // Structured data, e.g. {"@type": "BlogPosting", "author": {"name": "..."}}
+    let json_ld = || document.select(&selector(r#"script[type="application/ld+json"]"#))
+        .filter_map(|e| serde_json::from_str::<Value>(&e.text().collect::<String>()).ok())
+        .find_map(|v| json_ld_author(&v))
+        .and_then(|a| clean(&a));
+
+    meta.or_else(rel).or_else(json_ld)
+}
+
+/// The page's canonical URL, preferring `rel="canonical"` to `og:url`
+fn page_canonical_url(document: &Html) -> Option<String> {
+    let first = |css: &str, attr: &str| document.select(&selector(css))
+        .find_map(|e| e.value().attr(attr))
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+        .map(str::to_string);
+    first(r#"link[rel~="canonical"]"#, "href")
+        .or_else(|| first(r#"meta[property="og:url"]"#, "content"))
+}
+
+/// The Open Graph image, with its size if given
+fn page_image(document: &Html) -> Option<MediaImage> {
+    let property = |name: &str| document.select(&selector(&format!(r#"meta[property="{}"]"#, name)))
+        .find_map(|e| e.value().attr("content"))
+        .map(str::trim)
+        .filter(|v| !v.is_empty());
+    let url = property("og:image:secure_url").or_else(|| property("og:image"))?;
+    Some(MediaImage::new(url).with_size(property("og:image:width"), property("og:image:height")))
+}
+
+/// Search JSON-LD data (including arrays and `@graph`) for an author name
+fn json_ld_author(value: &Value) -> Option<String> {
+    match value {
+        Value::Array(items) => items.iter().find_map(json_ld_author),
+        Value::Object(map) => {
+            if let Some(author) = map.get("author") {
+                let name = match author {
+                    Value::String(name) => Some(name.clone()),
+                    Value::Object(person) => person.get("name").and_then(Value::as_str).map(str::to_string),
+                    Value::Array(people) => people.iter().find_map(|p| match p {
+                        Value::String(name) => Some(name.clone()),
+                        _ => p.get("name").and_then(Value::as_str).map(str::to_string),
+                    }),
+                    _ => None,
+                };
+                if name.is_some() {
+                    return name;
+                }
+            }
+            map.get("@graph").and_then(json_ld_author)
+        }
+        _ => None,
+    }
+}
+
+/// Parse one of the fixed selectors used above
+fn selector(css: &str) -> Selector {
+    Selector::parse(css).expect("metadata selectors are valid CSS")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn categories_from_all_sources_without_duplicates() {
+        let html = r#"<html><head>
+            <meta name="keywords" content="Rust, RSS ,  static sites">
+            <meta property="article:tag" content="rss">
+            <meta property="article:tag" content="Blogging">
+        </head><body>
+            <a href="/tags/travel" rel="tag">Travel</a>
+            <a href="/about" rel="author">Me</a>
+        </body></html>"#;
+        let metadata = extract_metadata(html);
+        assert_eq!(metadata.categories, vec!["Rust", "RSS", "static sites", "Blogging", "Travel"]);
+    }
+
+    #[test]
+    fn author_from_meta_first() {
+        let html = r#"<html><head><meta name="author" content="Jo Bloggs"></head>
+            <body><a rel="author" href="/me">Someone Else</a></body></html>"#;
+        assert_eq!(extract_metadata(html).author.as_deref(), Some("Jo Bloggs"));
+    }
+
+    #[test]
+    fn author_from_rel_link() {
+        let html = r#"<html><body><a rel="author" href="/me"> Sam
+            Smith </a></body></html>"#;
+        assert_eq!(extract_metadata(html).author.as_deref(), Some("Sam Smith"));
+    }
+
+    #[test]
+    fn author_from_json_ld_graph() {
+        let html = r#"<html><head><script type="application/ld+json">
+            {"@context": "https://schema.org", "@graph": [
+                {"@type": "WebSite", "name": "Blog"},
+                {"@type": "BlogPosting", "author": [{"@type": "Person", "name": "Ann Other"}]}
+            ]}
+        </script></head><body></body></html>"#;
+        assert_eq!(extract_metadata(html).author.as_deref(), Some("Ann Other"));
+    }
+
+    #[test]
+    fn open_graph_image() {
+        let html = r#"<html><head>
+            <meta property="og:image" content="images/lead.jpg">
+            <meta property="og:image:width" content="1200">
+        </head><body></body></html>"#;
+        let image = extract_metadata(html).image.unwrap();
+        assert_eq!((image.url.as_str(), image.width, image.height), ("images/lead.jpg", Some(1200), None));
+    }
+
+    #[test]
+    fn canonical_url_preferred_to_og_url() {
+        let html = r#"<html><head>
+            <meta property="og:url" content="https://site/og">
+            <link rel="canonical" href="https://site/blog/post/">
+        </head><body></body></html>"#;
+        assert_eq!(extract_metadata(html).canonical_url.as_deref(), Some("https://site/blog/post/"));
+
+        let html = r#"<html><head><meta property="og:url" content="https://site/og"></head></html>"#;
+        assert_eq!(extract_metadata(html).canonical_url.as_deref(), Some("https://site/og"));
+    }
+
+    #[test]
+    fn no_categories() {
+        assert!(extract_metadata("<html><body><p>Hi</p></body></html>").categories.is_empty());
+    }
+}
@@ -0,0 +1,179 @@
+//! iTunes podcast extensions for items and channels
+
+use id3::TagLike;
+use std::path::Path;
+
+use crate::utils;
+
+/// The iTunes podcast namespace, needed for all <itunes:*> elements
+pub const ITUNES_NAMESPACE: (&str, &str) = ("itunes", "http://www.itunes.com/dtds/podcast-1.0.dtd");
+
+/// Podcast fields for an <item>
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ItunesItem {
+    /// Running time, e.g. "1:02:03"
+    pub duration: Option<String>,
+    /// Episode number
+    pub episode: Option<u32>,
+    /// Whether the episode contains explicit content
+    pub explicit: Option<bool>,
+    /// URL of the episode artwork
+    pub image: Option<String>,
+    /// Plain text description of the episode
+    pub summary: Option<String>,
+}
+
+impl ItunesItem {
+    /// Read what podcast fields we can from an audio file's ID3 tag: the
+    /// duration from TLEN, the episode from the track number and the
+    /// summary from the first comment. Files without a tag give no fields.
+    pub fn from_audio_file(path: impl AsRef<Path>) -> Self {
+        let Ok(tag) = id3::Tag::read_from_path(path) else {
+            return ItunesItem::default();
+        };
+        let summary = tag.comments()
+            .map(|c| c.text.trim().to_string())
+            .find(|t| !t.is_empty());
+        ItunesItem {
+            // TLEN is in milliseconds
+            duration: tag.duration().map(|ms| format_duration(u64::from(ms) / 1000)),
+            episode: tag.track(),
+            summary,
+            ..ItunesItem::default()
+        }
+    }
+
+    /// Fill any fields not already set from another set of fields
+    pub fn or(self, other: ItunesItem) -> Self {
+        ItunesItem {
+            duration: self.duration.or(other.duration),
+            episode: self.episode.or(other.episode),
+            explicit: self.explicit.or(other.explicit),
+            image: self.image.or(other.image),
+            summary: self.summary.or(other.summary),
+        }
+    }
+
+    /// Whether no podcast fields are set
+    pub fn is_empty(&self) -> bool {
+        *self == ItunesItem::default()
+    }
+
+    /// The <itunes:*> elements for the fields which are set
+    pub fn to_xml_elements(&self) -> Vec<String> {
+        let mut elements = Vec::new();
+        if let Some(duration) = &self.duration {
+            elements.push(format!("<itunes:duration>{}</itunes:duration>", utils::escape_xml(duration)));
+        }
+        if let Some(episode) = self.episode {
+            elements.push(format!("<itunes:episode>{}</itunes:episode>", episode));
+        }
+        if let Some(explicit) = self.explicit {
+            elements.push(explicit_xml(explicit));
+        }
+        if let Some(image) = &self.image {
+            elements.push(image_xml(image));
+        }
+        if let Some(summary) = &self.summary {
+            elements.push(format!("<itunes:summary>{}</itunes:summary>", utils::escape_xml(summary)));
+        }
+        elements
+    }
+}
+
+/// Podcast fields for the <channel>
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ItunesChannel {
+    /// Whether the podcast contains explicit content
+    pub explicit: Option<bool>,
+    /// URL of the podcast artwork
+    pub image: Option<String>,
+    /// Plain text description of the podcast
+    pub summary: Option<String>,
+}
+
+impl ItunesChannel {
+    /// Whether no podcast fields are set
+    pub fn is_empty(&self) -> bool {
+        *self == ItunesChannel::default()
+    }
+
+    /// The (tag name, element XML) pairs for the fields which are set
+    pub fn to_xml_elements(&self) -> Vec<(&'static str, String)> {
+        let mut elements = Vec::new();
+        if let Some(explicit) = self.explicit {
+            elements.push(("itunes:explicit", explicit_xml(explicit)));
+        }
+        if let Some(image) = &self.image {
+            elements.push(("itunes:image", image_xml(image)));
+        }
+        if let Some(summary) = &self.summary {
+            elements.push(("itunes:summary", format!("<itunes:summary>{}</itunes:summary>", utils::escape_xml(summary))));
+        }
+        elements
+    }
+}
+
+/// Format a number of seconds as H:MM:SS, or MM:SS for under an hour
+pub fn format_duration(seconds: u64) -> String {
+    let (hours, minutes, seconds) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    match hours {
+        0 => format!("{:02}:{:02}", minutes, seconds),
+        _ => format!("{}:{:02}:{:02}", hours, minutes, seconds),
+    }
+}
+
+fn explicit_xml(explicit: bool) -> String {
+    format!("<itunes:explicit>{}</itunes:explicit>", explicit)
+}
+
+fn image_xml(url: &str) -> String {
+    format!(r#"<itunes:image href="{}" />"#, utils::escape_xml(url))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations() {
+        assert_eq!(format_duration(59), "00:59");
+        assert_eq!(format_duration(605), "10:05");
+        assert_eq!(format_duration(3723), "1:02:03");
+    }
+
+    #[test]
+    fn fields_from_id3_tag() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("episode.mp3");
+        let mut tag = id3::Tag::new();
+        tag.set_duration(3_723_000);
+        tag.set_track(7);
+        tag.add_frame(id3::frame::Comment {
+            lang: "eng".to_string(),
+            description: String::new(),
+            text: "All about RSS".to_string(),
+        });
+        std::fs::write(&path, []).unwrap();
+        tag.write_to_path(&path, id3::Version::Id3v24).unwrap();
+
+        let itunes = ItunesItem::from_audio_file(&path);
+        assert_eq!(itunes.duration.as_deref(), Some("1:02:03"));
+        assert_eq!(itunes.episode, Some(7));
+        assert_eq!(itunes.summary.as_deref(), Some("All about RSS"));
+    }
+
+    #[test]
+    fn flags_take_precedence_over_file() {
+        let flags = ItunesItem { episode: Some(2), explicit: Some(false), ..ItunesItem::default() };
+        let file = ItunesItem { episode: Some(7), duration: Some("10:00".to_string()), ..ItunesItem::default() };
+        let itunes = flags.or(file);
+        assert_eq!(itunes.episode, Some(2));
+        assert_eq!(itunes.duration.as_deref(), Some("10:00"));
+        assert_eq!(itunes.to_xml_elements(), vec![
+            "<itunes:duration>10:00</itunes:duration>",
+            "<itunes:episode>2</itunes:episode>",
+            "<itunes:explicit>false</itunes:explicit>",
+        ]);
+    }
+}
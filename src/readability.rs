@@ -0,0 +1,131 @@
+//! Finding the main content of a page without being told where it is
+//!
+//! A simple take on browser "reader modes": each paragraph of text scores
+//! points for the elements around it, adjusted by tag and by class names
+//! suggesting content or clutter, and cut down for link-heavy elements
+//! such as menus and lists of related posts. The best scoring element wins.
+
+use regex::Regex;
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+
+/// The `--selector` value asking for the main content to be found automatically
+pub const AUTO_SELECTOR: &str = "auto";
+
+/// Elements whose text is never part of the main content
+const CLUTTER_TAGS: [&str; 6] = ["aside", "footer", "form", "header", "nav", "script"];
+
+/// Blocks of text shorter than this (in characters) don't count
+const MIN_BLOCK_LENGTH: usize = 25;
+
+/// Find the element most likely to hold the main content of a page
+pub fn main_content(document: &Html) -> Option<ElementRef<'_>> {
+    let blocks = Selector::parse("p, pre, blockquote, td").ok()?;
+    let positive = Regex::new(r"(?i)article|body|content|entry|main|post|story|text").ok()?;
+    let negative = Regex::new(r"(?i)\bad\b|banner|comment|footer|menu|nav|promo|related|share|sidebar|social|sponsor|widget").ok()?;
+
+    // Each block of text scores points for its parent and, at half
+    // weight, its grandparent (in the order they're first found, so
+    // the earliest wins a tie)
+    let mut candidates: Vec<(ElementRef, f64)> = Vec::new();
+    let mut index = HashMap::new();
+    for block in document.select(&blocks) {
+        if ancestor_elements(block).any(|e| CLUTTER_TAGS.contains(&e.value().name())) {
+            continue;
+        }
+        let text: String = block.text().collect();
+        let length = text.trim().chars().count();
+        if length < MIN_BLOCK_LENGTH {
+            continue;
+        }
+
+        // Longer, comma-rich text is more likely to be prose
+        let score = 1.0 + text.matches(',').count() as f64 + (length as f64 / 100.0).min(3.0);
+        for (ancestor, weight) in ancestor_elements(block).take(2).zip([1.0, 0.5]) {
+            let i = *index.entry(ancestor.id()).or_insert_with(|| {
+                candidates.push((ancestor, initial_score(ancestor, &positive, &negative)));
+                candidates.len() - 1
+            });
+            candidates[i].1 += score * weight;
+        }
+    }
+
+    candidates.into_iter()
+        .map(|(element, score)| (element, score * (1.0 - link_density(element))))
+        .fold(None, |best: Option<(ElementRef, f64)>, (element, score)| match best {
+            Some((_, best_score)) if best_score >= score => best,
+            _ => Some((element, score)),
+        })
+        .map(|(element, _)| element)
+}
+
+/// The elements containing an element, innermost first
+fn ancestor_elements(element: ElementRef<'_>) -> impl Iterator<Item = ElementRef<'_>> {
+    element.ancestors().filter_map(ElementRef::wrap)
+}
+
+/// A head start (or handicap) for an element based on its tag, class and id
+fn initial_score(element: ElementRef, positive: &Regex, negative: &Regex) -> f64 {
+    let tag_score = match element.value().name() {
+        "article" | "main" => 10.0,
+        "div" => 5.0,
+        "section" | "pre" | "td" | "blockquote" => 3.0,
+        "ol" | "ul" | "li" | "dl" | "dd" | "dt" | "form" => -3.0,
+        "body" => -5.0,
+        _ => 0.0,
+    };
+    let names = [element.value().attr("class"), element.value().id()];
+    let name_score: f64 = names.iter()
+        .flatten()
+        .map(|name| match (positive.is_match(name), negative.is_match(name)) {
+            (_, true) => -25.0,
+            (true, false) => 25.0,
+            (false, false) => 0.0,
+        })
+        .sum();
+    tag_score + name_score
+}
+
+/// The proportion of an element's text that is inside links
+fn link_density(element: ElementRef) -> f64 {
+    let text_length: usize = element.text().map(|t| t.trim().chars().count()).sum();
+    if text_length == 0 {
+        return 0.0;
+    }
+    let Ok(links) = Selector::parse("a") else { return 0.0 };
+    let link_length: usize = element.select(&links)
+        .flat_map(|a| a.text())
+        .map(|t| t.trim().chars().count())
+        .sum();
+    link_length as f64 / text_length as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn finds_article_text() {
+        let page = Html::parse_document(r#"<html><body>
+            <header><p>Welcome to my site, the home of all sorts of things.</p></header>
+            <div id="menu"><a href="/">Home</a> <a href="/blog">Blog</a> <a href="/about">About</a></div>
+            <div class="entry">
+                <h1>Title</h1>
+                <p>This is the first paragraph of the post, with a comma or two, which is long.</p>
+                <p>This is the second paragraph of the post, also long enough to count for something.</p>
+            </div>
+            <div class="related">
+                <p><a href="/a">A related post with a long enough title to count for something</a></p>
+            </div>
+            <footer><p>Copyright, all rights reserved, do not copy this text anywhere.</p></footer>
+        </body></html>"#);
+        let element = main_content(&page).unwrap();
+        assert_eq!(element.value().attr("class"), Some("entry"));
+    }
+
+    #[test]
+    fn nothing_found_without_text() {
+        let page = Html::parse_document("<html><body><nav><p>Only a menu, nothing to read here at all.</p></nav></body></html>");
+        assert!(main_content(&page).is_none());
+    }
+}
@@ -1,7 +1,6 @@
 use chrono::{DateTime, ParseError, FixedOffset, Utc};
 use url::Url;
-use std::fs::{File, OpenOptions};
-use std::io::{Read, Write};
+use similar::TextDiff;
 
 /// Escape XML special characters
 pub fn escape_xml(text: &str) -> String {
@@ -12,6 +11,15 @@ pub fn escape_xml(text: &str) -> String {
         .replace("'", "&apos;")
 }
 
+/// Unescape the predefined XML entities
+pub fn unescape_xml(text: &str) -> String {
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
 pub fn now_rfc2822() -> String {
     Utc::now().to_rfc2822()
 }
@@ -71,11 +79,12 @@ pub fn merge_url_and_fragment(base_url: &str, fragment: &str) -> Result<String,
 
 /// Merges a base URL and a path fragment and removes any overlaps by
 /// handling the case where a base URL and relative path might contain
-/// common segments, e.g. avoiding:
+/// common segments (however many), e.g. avoiding:
 // https://site/blog  + blog/page.html -> https://site/blog/blog/page.html
+// https://site/a/b   + a/b/page.html  -> https://site/a/b/a/b/page.html
 pub fn merge_remove_overlap(base_url: &str, relative_path: &str) -> Result<String, url::ParseError> {
     // If relative_path is already a full URL, return it as-is
-    if relative_path.starts_with("http") {
+    if relative_path.starts_with("http://") || relative_path.starts_with("https://") {
         return Ok(relative_path.to_string());
     }
 
@@ -106,20 +115,23 @@ pub fn merge_remove_overlap(base_url: &str, relative_path: &str) -> Result<Strin
         .map(|segments| segments.filter(|s| !s.is_empty()).collect())
         .unwrap_or_default();
     
-    // Get the first segment of the relative path (if it has path segments)
-    let relative_first_segment = relative_path.split('/').next().unwrap_or("");
-    
-    // Check if the relative path starts with a segment that's the same as the last segment of base URL path
-    // This indicates potential overlap that we want to avoid
-    let should_strip_base_path = !base_segments.is_empty() && 
-                                 !relative_first_segment.is_empty() &&
-                                 base_segments.last() == Some(&relative_first_segment);
-    
-    let result = if should_strip_base_path {
-        // Remove the last segment from the base URL path to avoid duplication
+    // Get the directory segments of the relative path (i.e. not the file name)
+    let relative_segments: Vec<&str> = relative_path.split('/').collect();
+    let relative_dirs = &relative_segments[..relative_segments.len() - 1];
+
+    // Find the longest run of segments at the end of the base URL path which
+    // the relative path starts with. This indicates overlap that we want to avoid
+    let overlap = (1..=base_segments.len().min(relative_dirs.len()))
+        .rev()
+        .find(|&n| base_segments[base_segments.len() - n..] == relative_dirs[..n])
+        .unwrap_or(0);
+
+    let result = if overlap > 0 {
+        // Remove the overlapping segments from the base URL path to avoid duplication
         let mut new_base = base.clone();
-        let new_path = if base_segments.len() > 1 {
-            format!("/{}/", base_segments[..base_segments.len() - 1].join("/"))
+        let kept = &base_segments[..base_segments.len() - overlap];
+        let new_path = if !kept.is_empty() {
+            format!("/{}/", kept.join("/"))
         } else {
             "/".to_string()
         };
@@ -133,31 +145,26 @@ pub fn merge_remove_overlap(base_url: &str, relative_path: &str) -> Result<Strin
     Ok(result.to_string())
 }
 
-/// Inserts text before a given text string in a given file path
-pub fn insert_before_text(file_path: &str, target_text: &str, insert_text: &str) -> std::io::Result<()> {
-    // Read the entire file content
-    let mut file = File::open(file_path)?;
-    let mut content = String::new();
-    file.read_to_string(&mut content)?;
-
-    // Find the first occurrence of target text
-    if let Some(pos) = content.find(target_text) {
-        // Create new content with insert_text before target_text
-        let mut new_content = String::new();
-        new_content.push_str(&content[..pos]);
-        new_content.push_str(insert_text);
-        new_content.push_str(target_text);
-        new_content.push_str(&content[pos + target_text.len()..]);
-
-        // Write back to the file
-        let mut file = OpenOptions::new()
-            .write(true)
-            .truncate(true)
-            .open(file_path)?;
-        file.write_all(new_content.as_bytes())?;
-    }
+/// Returns a copy of `content` with `insert_text` placed before the first
+/// occurrence of `target_text`, or `None` if the target text is not found
+pub fn insert_before(content: &str, target_text: &str, insert_text: &str) -> Option<String> {
+    let pos = content.find(target_text)?;
+
+    // Create new content with insert_text before target_text
+    let mut new_content = String::with_capacity(content.len() + insert_text.len());
+    new_content.push_str(&content[..pos]);
+    new_content.push_str(insert_text);
+    new_content.push_str(&content[pos..]);
+    Some(new_content)
+}
 
-    Ok(())
+/// Produces a unified diff between the old and new content of a file
+pub fn unified_diff(old: &str, new: &str, file_name: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .unified_diff()
+        .context_radius(3)
+        .header(&format!("a/{}", file_name), &format!("b/{}", file_name))
+        .to_string()
 }
 
 #[cfg(test)]
@@ -289,6 +296,13 @@ mod tests {
         assert_eq!(merged, "https://site/a/b/c/d/e.html")
     }
 
+    #[test]
+    fn merge_remove_overlap_several_segments() {
+        // Should remove an overlap of more than one segment
+        let merged = merge_remove_overlap("https://site/a/b", "a/b/page.html").unwrap();
+        assert_eq!(merged, "https://site/a/b/page.html")
+    }
+
     #[test]
     fn merge_remove_overlap_no_base_path() {
         // Should work when base URL has no path
@@ -303,4 +317,27 @@ mod tests {
         let merged = merge_remove_overlap("https://site/blogger/", "blog/page.html").unwrap();
         assert_eq!(merged, "https://site/blogger/blog/page.html")
     }
+
+    /******************** File insertion **********************/
+
+    #[test]
+    fn insert_before_places_text_ahead_of_target() {
+        let result = insert_before("<channel>\n</channel>", "</channel>", "<item/>\n").unwrap();
+        assert_eq!(result, "<channel>\n<item/>\n</channel>")
+    }
+
+    #[test]
+    fn insert_before_missing_target() {
+        assert_eq!(insert_before("<channel>", "</channel>", "<item/>"), None)
+    }
+
+    #[test]
+    fn unified_diff_shows_only_added_lines() {
+        let old = "<channel>\n<title>T</title>\n</channel>\n";
+        let new = insert_before(old, "</channel>", "<item/>\n").unwrap();
+        let diff = unified_diff(old, &new, "rss.xml");
+        assert!(diff.starts_with("--- a/rss.xml\n+++ b/rss.xml\n@@"));
+        assert!(diff.contains("\n+<item/>\n"));
+        assert_eq!(diff.lines().filter(|l| l.starts_with('-') && !l.starts_with("---")).count(), 0);
+    }
 }
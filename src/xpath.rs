@@ -0,0 +1,95 @@
+//! Selecting page content with XPath
+//!
+//! XPath can pick out elements by position or by their text, e.g.
+//! `//h2[contains(., 'Update')]/following-sibling::div[1]`, which CSS
+//! selectors can't. The parsed HTML is copied into an XML document for
+//! the XPath engine and the elements it selects are mapped back.
+
+use scraper::{ElementRef, Html, Node};
+use sxd_document::dom::{Document, Element};
+use sxd_document::Package;
+use sxd_xpath::nodeset::Node as XmlNode;
+use sxd_xpath::{Context, Factory, Value};
+
+use crate::error::{Error, Result};
+
+/// The elements an XPath expression selects in an HTML document, in page order
+pub fn select<'a>(document: &'a Html, xpath: &str) -> Result<Vec<ElementRef<'a>>> {
+    let invalid = |e: &dyn std::fmt::Display| Error::Parse(format!("invalid XPath '{}': {}", xpath, e));
+    let expression = Factory::new()
+        .build(xpath)
+        .map_err(|e| invalid(&e))?
+        .ok_or_else(|| Error::Parse(format!("invalid XPath '{}': it's empty", xpath)))?;
+
+    // Copy the HTML into an XML document, noting which element is which
+    let package = Package::new();
+    let xml = package.as_document();
+    let mut elements = Vec::new();
+    let root = copy_element(xml, document.root_element(), &mut elements);
+    xml.root().append_child(root);
+
+    let value = expression.evaluate(&Context::new(), xml.root()).map_err(|e| invalid(&e))?;
+    let Value::Nodeset(nodes) = value else {
+        return Err(Error::Parse(format!("XPath '{}' doesn't select elements", xpath)));
+    };
+    Ok(nodes.document_order()
+        .into_iter()
+        .filter_map(|node| match node {
+            XmlNode::Element(selected) => elements.iter().find(|(e, _)| *e == selected).map(|(_, html)| *html),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Copy an HTML element with its attributes, text and child elements
+/// (comments etc. are left out) into an XML document
+fn copy_element<'d, 'a>(xml: Document<'d>, html: ElementRef<'a>, elements: &mut Vec<(Element<'d>, ElementRef<'a>)>) -> Element<'d> {
+    let element = xml.create_element(html.value().name());
+    for (name, value) in html.value().attrs() {
+        element.set_attribute_value(name, value);
+    }
+    elements.push((element, html));
+
+    for child in html.children() {
+        match child.value() {
+            Node::Element(_) => {
+                if let Some(child) = ElementRef::wrap(child) {
+                    element.append_child(copy_element(xml, child, elements));
+                }
+            }
+            Node::Text(text) => element.append_child(xml.create_text(text)),
+            _ => {}
+        }
+    }
+    element
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PAGE: &str = r#"<html><body>
+        <h2>Intro</h2><div>First</div>
+        <h2>Update <!-- new --></h2><div class="a">Second</div><div>Third</div>
+    </body></html>"#;
+
+    #[test]
+    fn selects_by_text_and_position() {
+        let page = Html::parse_document(PAGE);
+        let selected = select(&page, "//h2[contains(., 'Update')]/following-sibling::div[1]").unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(selected[0].value().attr("class"), Some("a"));
+
+        let divs = select(&page, "//div").unwrap();
+        let text: Vec<String> = divs.iter().map(|d| d.text().collect()).collect();
+        assert_eq!(text, ["First", "Second", "Third"]);
+    }
+
+    #[test]
+    fn invalid_expressions() {
+        let page = Html::parse_document(PAGE);
+        assert!(matches!(select(&page, "//div["), Err(Error::Parse(_))));
+        assert!(matches!(select(&page, "count(//div)"), Err(Error::Parse(_))));
+        assert!(select(&page, "//article").unwrap().is_empty());
+    }
+}